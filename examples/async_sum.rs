@@ -0,0 +1,32 @@
+use futures::{SinkExt, StreamExt};
+use ipc_orchestrator::message::Message;
+use ipc_orchestrator::AsyncClient;
+use std::convert::TryFrom;
+use std::time::Instant;
+
+/// Same as sum example, implemented with async client
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut client = AsyncClient::connect().await?;
+    let mut numbers = client.subscribe("generate");
+
+    let start = Instant::now();
+
+    let mut sum = 0.0;
+    while let Some(msg) = numbers.next().await {
+        let num = f64::from_le_bytes(<[u8; 8]>::try_from(&msg.data[..])?);
+
+        sum += num;
+        client
+            .send(Message {
+                topic: "sum".to_string(),
+                data: sum.to_le_bytes().to_vec(),
+            })
+            .await?;
+    }
+
+    let ms = start.elapsed().as_millis();
+    println!("total sum {} in {}ms", sum, ms);
+
+    Ok(())
+}
@@ -1,4 +1,6 @@
+use futures::FutureExt;
 use ipc_orchestrator::orchestrator;
+use std::time::Duration;
 use tokio::process::Command;
 
 #[tokio::main]
@@ -32,8 +34,10 @@ async fn main() -> anyhow::Result<()> {
     orchestra.route_topic_to_bridge("sum", "write")?;
     orchestra.pipe_routes_via_crossbeam()?;
 
-    // Killing it hard since some spawned futures might still run
-    match orchestra.run().await {
+    // Stop pipeline gracefully on Ctrl+C,
+    // killing it hard on failure since some spawned futures might still run
+    let ctrl_c = tokio::signal::ctrl_c().map(|_| ());
+    match orchestra.run_until(ctrl_c, Duration::from_secs(5)).await {
         Err(_) => std::process::exit(1),
         Ok(_) => Ok(()),
     }
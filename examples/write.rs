@@ -1,10 +1,12 @@
-use ipc_orchestrator::connect_ipc_server;
+use ipc_orchestrator::{connect_ipc_server, notify_ready, Heartbeat};
 use std::convert::TryFrom;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 fn main() -> anyhow::Result<()> {
     let channel = connect_ipc_server().expect("failed to connect to server");
-    let (_tx, rx) = channel.split().expect("failed to split channel");
+    let (tx, rx) = channel.split().expect("failed to split channel");
+    notify_ready(&tx).expect("failed to notify readiness");
+    Heartbeat::new(tx, Duration::from_millis(500)).spawn();
 
     let start = Instant::now();
 
@@ -0,0 +1,78 @@
+//! Run pipeline described in TOML config file, see `ipc_orchestrator::config`
+//!
+//! Usage: ipc-orchestrator [--check] <config.toml>
+//!
+//! With `--check` config is only validated, no processes are started.
+//! Pipeline is stopped gracefully on Ctrl+C.
+
+use futures::FutureExt;
+use ipc_orchestrator::config::Config;
+use log::{error, info};
+
+const USAGE: &str = "Usage: ipc-orchestrator [--check] <config.toml>";
+
+fn main() {
+    init_log_engine();
+
+    let mut check = false;
+    let mut path = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--check" => check = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => exit_with_usage(),
+        }
+    }
+    let path = path.unwrap_or_else(|| exit_with_usage());
+
+    let config = match Config::from_file(&path).and_then(|c| c.validate().map(|_| c)) {
+        Ok(config) => config,
+        Err(err) => {
+            error!("{:#}", err);
+            std::process::exit(2);
+        }
+    };
+    if check {
+        info!("{} is valid", path);
+        return;
+    }
+
+    let mut runtime = tokio::runtime::Runtime::new().expect("failed to start tokio runtime");
+    let result = runtime.block_on(async {
+        let orchestra = config.connect().await?;
+        let ctrl_c = tokio::signal::ctrl_c().map(|_| ());
+        orchestra.run_until(ctrl_c, config.grace()).await
+    });
+    match result {
+        // Processes terminated by requested shutdown are fine
+        Ok(report) if report.errors.is_empty() && report.killed.is_empty() => {}
+        Ok(report) => {
+            error!("pipeline did not stop cleanly: {:?}", report);
+            std::process::exit(1);
+        }
+        Err(err) => {
+            error!("{:#}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn exit_with_usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
+
+fn init_log_engine() {
+    let mut builder = pretty_env_logger::formatted_timed_builder();
+    builder
+        .filter_level(log::LevelFilter::Info)
+        .default_format_module_path(true);
+    if let Ok(filters) = std::env::var("RUST_LOG") {
+        builder.parse_filters(&filters);
+    }
+    builder.init();
+}
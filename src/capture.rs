@@ -0,0 +1,258 @@
+//! Capture of routed messages to file and its replay
+//!
+//! `ConnectedOrchestrator::record` taps topics in router and appends every message
+//! with its source bridge and time since recording started to capture file.
+//! `ConnectedOrchestrator::replay` injects captured messages into running router
+//! as if their source bridges sent them, at original pace or as fast as possible,
+//! so that a consumer might be tested against recorded input.
+//!
+//! Capture file starts with magic bytes, followed by records:
+//! little endian u32 length and bincode encoded `Record`.
+//! Recording appends to existing capture, time of every recording starts from zero.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use ipc_orchestrator::{CaptureReader, CaptureWriter, Record};
+//!
+//! let path = std::env::temp_dir().join(format!("capture-{}.bin", std::process::id()));
+//! let record = Record {
+//!     offset: Duration::from_millis(10),
+//!     source: Some("generate".to_owned()),
+//!     topic: "generate".to_owned(),
+//!     data: vec![1, 2, 3],
+//! };
+//! let mut writer = CaptureWriter::append(&path).unwrap();
+//! writer.write(&record).unwrap();
+//! writer.flush().unwrap();
+//! let records: Vec<Record> = CaptureReader::open(&path).unwrap().map(Result::unwrap).collect();
+//! assert_eq!(records, vec![record]);
+//! # std::fs::remove_file(&path).unwrap();
+//! ```
+
+use crate::message::{Headers, Message};
+use anyhow::{anyhow, Context};
+use crossbeam::channel;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Beginning of every capture file, the last byte is format version
+const MAGIC: &[u8; 8] = b"IPCCAP\x00\x01";
+
+/// Message captured from router
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    /// Time since recording started
+    pub offset: Duration,
+    /// Bridge which sent message
+    pub source: Option<String>,
+    pub topic: String,
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+}
+
+/// How fast captured messages are replayed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pace {
+    /// Messages are delayed as they were recorded
+    Original,
+    /// Messages are injected as fast as possible
+    Fast,
+}
+
+/// Appends records to capture file
+pub struct CaptureWriter {
+    file: BufWriter<File>,
+}
+
+impl CaptureWriter {
+    /// Open capture file for appending, file is created if it does not exist
+    pub fn append(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .with_context(|| format!("failed to open capture {}", path.display()))?;
+        if file.metadata()?.len() == 0 {
+            file.write_all(MAGIC)?;
+        } else {
+            check_magic(&mut file, path)?;
+        }
+        Ok(Self {
+            file: BufWriter::new(file),
+        })
+    }
+
+    /// Append record, it might be buffered until `flush`
+    pub fn write(&mut self, record: &Record) -> anyhow::Result<()> {
+        let buf = bincode::serialize(record)?;
+        self.file.write_all(&(buf.len() as u32).to_le_bytes())?;
+        self.file.write_all(&buf)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// Iterator over records of capture file
+pub struct CaptureReader {
+    file: BufReader<File>,
+}
+
+impl CaptureReader {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let mut file = File::open(path)
+            .with_context(|| format!("failed to open capture {}", path.display()))?;
+        check_magic(&mut file, path)?;
+        Ok(Self {
+            file: BufReader::new(file),
+        })
+    }
+
+    fn read(&mut self) -> anyhow::Result<Option<Record>> {
+        let mut len = [0u8; 4];
+        match self.file.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
+        let mut buf = vec![0u8; u32::from_le_bytes(len) as usize];
+        self.file
+            .read_exact(&mut buf)
+            .context("capture is truncated")?;
+        Ok(Some(bincode::deserialize(&buf)?))
+    }
+}
+
+impl Iterator for CaptureReader {
+    type Item = anyhow::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
+
+fn check_magic(file: &mut File, path: &Path) -> anyhow::Result<()> {
+    let mut magic = [0u8; 8];
+    match file.read_exact(&mut magic) {
+        Ok(()) if &magic == MAGIC => Ok(()),
+        _ => Err(anyhow!("{} is not a capture file", path.display())),
+    }
+}
+
+/// Write records tapped in router until router stops, failed write stops recording only
+pub(crate) fn record(records: channel::Receiver<Record>, mut writer: CaptureWriter, path: &str) {
+    while let Ok(record) = records.recv() {
+        let mut res = writer.write(&record);
+        // Capture is complete up to the latest message whenever recorder waits
+        if records.is_empty() {
+            res = res.and_then(|()| writer.flush());
+        }
+        if let Err(err) = res {
+            error!("recording to {} failed: {}", path, err);
+            return;
+        }
+    }
+    if let Err(err) = writer.flush() {
+        error!("recording to {} failed: {}", path, err);
+    }
+}
+
+/// Inject records into router with `inject` at `pace`, returns number of injected messages
+pub(crate) fn replay(
+    records: CaptureReader,
+    pace: Pace,
+    mut inject: impl FnMut(Message, Headers) -> anyhow::Result<()>,
+) -> anyhow::Result<usize> {
+    let start = Instant::now();
+    let mut elapsed = Duration::from_secs(0);
+    let mut last = Duration::from_secs(0);
+    let mut count = 0;
+    for record in records {
+        let record = record?;
+        if pace == Pace::Original {
+            // Recording appended to capture starts from zero, it follows previous one right away
+            elapsed += record.offset.checked_sub(last).unwrap_or_default();
+            last = record.offset;
+            if let Some(wait) = (start + elapsed).checked_duration_since(Instant::now()) {
+                std::thread::sleep(wait);
+            }
+        }
+        let headers = Headers {
+            source: record.source,
+            timestamp: Some(SystemTime::now()),
+            ..Default::default()
+        };
+        let msg = Message {
+            topic: record.topic,
+            data: record.data,
+        };
+        inject(msg, headers)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("capture-{}-{}.bin", name, std::process::id()))
+    }
+
+    fn record(topic: &str) -> Record {
+        Record {
+            offset: Duration::from_millis(1),
+            source: None,
+            topic: topic.to_owned(),
+            data: vec![1, 2, 3],
+        }
+    }
+
+    #[test]
+    fn truncated_record() {
+        let path = capture("truncated");
+        let mut writer = CaptureWriter::append(&path).unwrap();
+        writer.write(&record("first")).unwrap();
+        writer.write(&record("second")).unwrap();
+        writer.flush().unwrap();
+        let len = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 2)
+            .unwrap();
+
+        let mut reader = CaptureReader::open(&path).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap().topic, "first");
+        let err = reader.next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "capture is truncated");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn bad_magic() {
+        let path = capture("magic");
+        std::fs::write(&path, b"IPCCAP\x00\x02 newer version").unwrap();
+        let err = CaptureReader::open(&path).err().unwrap();
+        assert!(err.to_string().ends_with("is not a capture file"));
+        // Existing file of other format is not appended to
+        assert!(CaptureWriter::append(&path).is_err());
+        std::fs::write(&path, b"IPC").unwrap();
+        assert!(CaptureReader::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
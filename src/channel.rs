@@ -36,6 +36,9 @@ where
     pub fn rx_take(&mut self) -> Option<IpcReceiver<T>> {
         self.1.take()
     }
+    pub fn rx(&self) -> Option<&IpcReceiver<T>> {
+        self.1.as_ref()
+    }
 }
 
 unsafe impl<T> Send for Channel<T> where T: Send {}
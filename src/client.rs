@@ -0,0 +1,662 @@
+//! Client side of IPC for child processes
+//!
+//! Child connects to orchestrator with `connect_ipc_server`, reports readiness with `notify_ready`
+//! and keeps itself monitored with `Heartbeat`.
+//! Message headers are sent with `send_with_headers` and received with `recv_with_headers`
+//! once child asked for them with `enable_headers`.
+//! These are available with `client` feature alone, without orchestrator dependencies.
+//!
+//! `AsyncClient` performs IPC handshake without blocking async runtime
+//! and exposes IPC channel as `Stream<Item = Message>` and `Sink<Message>`,
+//! so that child can `select!` over IPC traffic and its other I/O.
+//! Receiving is done by background thread, which works with any async runtime.
+//!
+//! Child might declare topics it is interested in with `subscribe` and `unsubscribe`,
+//! router applies them live in addition to routes configured by orchestrator.
+//!
+//! Messages of topics `subscribe`d to are delivered to their subscription streams,
+//! topic might be pattern like `metrics.*` or `sensors.#`, see `TopicPattern`,
+//! all the other messages are delivered to the client stream.
+//!
+//! `AsyncClient::request` sends request and waits for reply, which router delivers only
+//! to requester. Responder receives requests from `serve` stream and answers them with `reply`,
+//! synchronous responder uses `recv_with_headers` and `reply` function.
+//!
+//! Child linked to its peers with `Orchestrator::link` receives direct channels
+//! to and from them with `connect_links`, messages sent over links bypass router.
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use std::time::Duration;
+//! use ipc_orchestrator::{AsyncClient, RequestError};
+//!
+//! # async fn run(client: AsyncClient, responder: AsyncClient) -> anyhow::Result<()> {
+//! // Responder process
+//! let mut requests = responder.serve("double");
+//! while let Some(request) = requests.next().await {
+//!     let doubled: Vec<u8> = request.message.data.iter().map(|x| x * 2).collect();
+//!     responder.reply(&request, doubled)?;
+//! }
+//! // Requester process
+//! match client.request("double", vec![1, 2], Duration::from_secs(1)).await {
+//!     Ok(reply) => assert_eq!(reply.data, vec![2, 4]),
+//!     Err(err) => match err.downcast_ref::<RequestError>() {
+//!         Some(RequestError::NoResponder { .. }) => { /* nobody serves `double` */ }
+//!         Some(RequestError::Timeout { .. }) => { /* responder is too slow */ }
+//!         _ => return Err(err),
+//!     },
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Example
+//!
+//! ```no_run
+//! use futures::{SinkExt, StreamExt};
+//! use ipc_orchestrator::message::Message;
+//! use ipc_orchestrator::AsyncClient;
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let mut client = AsyncClient::connect().await?;
+//! let mut numbers = client.subscribe("numbers");
+//! client.publish("started", vec![])?;
+//! while let Some(msg) = numbers.next().await {
+//!     client.send(Message { topic: "echo".to_owned(), data: msg.data }).await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! # Readiness
+//!
+//! Client enables headers and subscribes before notifying orchestrator it is ready,
+//! which `Probe::ready_message` accepts. Example below runs itself as its child process:
+//!
+//! ```
+//! # #[cfg(feature = "orchestrator")] {
+//! use futures::StreamExt;
+//! use ipc_orchestrator::{notify_ready, orchestrator, AsyncClient, Probe};
+//! use std::time::Duration;
+//! use tokio::process::Command;
+//!
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! if std::env::var(ipc_orchestrator::IPC_SERVER_ENV_VAR).is_ok() {
+//!     // Child process
+//!     let client = AsyncClient::connect().await?;
+//!     let mut greetings = client.subscribe("greeting");
+//!     notify_ready(&client.sender())?;
+//!     greetings.next().await;
+//!     client.publish("greeted", vec![])?;
+//!     // Channel closes when orchestrator shuts down
+//!     greetings.next().await;
+//!     return Ok(());
+//! }
+//! let mut orchestrator = orchestrator().ipc(true);
+//! orchestrator.probe("child", Probe::ready_message())?;
+//! orchestrator.start("child", &mut Command::new(std::env::current_exe()?))?;
+//! let mut orchestrator = orchestrator.connect().await?;
+//! let mut greeted = orchestrator.tap("greeted")?;
+//! orchestrator.pipe_routes()?;
+//! orchestrator.publish("greeting", b"hello".to_vec())?;
+//! greeted.next().await;
+//! orchestrator.shutdown(Duration::from_secs(1)).await?;
+//! # Ok::<_, anyhow::Error>(())
+//! # }).unwrap();
+//! # }
+//! ```
+
+use crate::message::{Headers, Message, NO_RESPONDER_TOPIC, REPLY_TOPIC};
+use crate::topic::TopicPattern;
+use crate::{Channel, Receiver, Sender, IPC_LINKS_ENV_VAR, IPC_SERVER_ENV_VAR};
+use futures::channel::{mpsc, oneshot};
+use futures::executor::block_on;
+use futures::sink::{Sink, SinkExt};
+use futures::stream::Stream;
+use ipc_channel::ipc::{self, IpcSender};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::pin::Pin;
+use std::sync::mpsc as std_mpsc;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// This is helper function for implementing child processes
+/// Child process will automatically connect to the IPC server
+/// passed in the env var "IPC_SERVER".
+/// This env var is injected by orchestrator.
+/// Execution blocks until connected
+pub fn connect_ipc_server() -> anyhow::Result<Channel> {
+    let ipc_output = std::env::var(IPC_SERVER_ENV_VAR)?;
+    println!("Connecting to server: {}", ipc_output);
+    let tx = IpcSender::connect(ipc_output.clone())?;
+    let (ch1, ch2) = Channel::duplex()?;
+    println!("Connected, sending Channel to server: {}", ipc_output);
+    tx.send(ch1)?;
+    Ok(ch2)
+}
+
+/// Direct channels between process and its peers, see `connect_links`
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PeerLinks {
+    /// Senders to peers by peer name
+    pub to: HashMap<String, Sender>,
+    /// Receivers from peers by peer name
+    pub from: HashMap<String, Receiver>,
+}
+
+/// Receive links to and from peers declared with `Orchestrator::link`,
+/// links server is passed by orchestrator in "IPC_LINKS" env var.
+/// Execution blocks until links are received
+pub fn connect_links() -> anyhow::Result<PeerLinks> {
+    let server = std::env::var(IPC_LINKS_ENV_VAR)?;
+    let tx = IpcSender::<IpcSender<PeerLinks>>::connect(server)?;
+    let (links_tx, links_rx) = ipc::channel()?;
+    tx.send(links_tx)?;
+    links_rx
+        .recv()
+        .map_err(|err| anyhow::anyhow!("failed to receive links: {:?}", err))
+}
+
+/// Report readiness to orchestrator, when process is started with `Probe::ready_message()`
+/// this should be the first message sent via channel received from `connect_ipc_server`
+pub fn notify_ready(tx: &Sender) -> anyhow::Result<()> {
+    tx.send(Message::ready())?;
+    Ok(())
+}
+
+/// Ask orchestrator to send every message routed to this process with its headers
+pub fn enable_headers(tx: &Sender) -> anyhow::Result<()> {
+    tx.send(Message::headers_on())?;
+    Ok(())
+}
+
+/// Ask router to route `topic` or pattern like `metrics.*` to this process,
+/// router started with `pipe_routes` applies it right away
+pub fn subscribe(tx: &Sender, topic: &str) -> anyhow::Result<()> {
+    tx.send(Message::subscribe(topic))?;
+    Ok(())
+}
+
+/// Ask router to stop routing `topic` or pattern to this process
+pub fn unsubscribe(tx: &Sender, topic: &str) -> anyhow::Result<()> {
+    tx.send(Message::unsubscribe(topic))?;
+    Ok(())
+}
+
+/// Send message with `headers`, e.g. correlation id or own sequence numbers,
+/// source bridge is stamped by router
+pub fn send_with_headers(tx: &Sender, msg: Message, headers: &Headers) -> anyhow::Result<()> {
+    tx.send(msg.with_headers(headers))?;
+    Ok(())
+}
+
+/// Blocking receive of message with its headers, headers are empty
+/// when message was sent without them, see `enable_headers`
+pub fn recv_with_headers(rx: &Receiver) -> anyhow::Result<(Message, Headers)> {
+    let (msg, headers) = rx.recv()?.split_headers();
+    Ok((msg, headers.unwrap_or_default()))
+}
+
+/// Reply with `data` to request received with `recv_with_headers`, `request` are its headers.
+/// Reply is delivered only to requester, responder should call `enable_headers` before
+pub fn reply(tx: &Sender, request: &Headers, data: Vec<u8>) -> anyhow::Result<()> {
+    let headers = Headers {
+        correlation_id: request.correlation_id,
+        ..Default::default()
+    };
+    let msg = Message {
+        topic: REPLY_TOPIC.to_owned(),
+        data,
+    };
+    send_with_headers(tx, msg, &headers)
+}
+
+/// Child side heartbeat sender
+pub struct Heartbeat {
+    tx: Sender,
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl Heartbeat {
+    /// Heartbeat sending at most one message per `interval`,
+    /// interval should be shorter than the one orchestrator expects
+    pub fn new(tx: Sender, interval: Duration) -> Self {
+        Self {
+            tx,
+            interval,
+            last: None,
+        }
+    }
+
+    /// Send heartbeat if interval elapsed since the last one,
+    /// call it from processing loop so that hung loop stops heartbeats
+    pub fn beat(&mut self) -> anyhow::Result<()> {
+        if self.last.is_some_and(|last| last.elapsed() < self.interval) {
+            return Ok(());
+        }
+        self.tx.send(Message::heartbeat())?;
+        self.last = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Send heartbeats from background thread until channel closes,
+    /// suitable for processes which block waiting for messages
+    pub fn spawn(mut self) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || {
+            while self.beat().is_ok() {
+                std::thread::sleep(self.interval);
+            }
+        })
+    }
+}
+
+/// Capacity of client and subscription streams, receiving thread waits when it is reached
+const STREAM_CAPACITY: usize = 64;
+
+/// Subscribers by topic or pattern they subscribed to
+type Subscriptions<T> = Arc<Mutex<HashMap<String, (TopicPattern, Vec<mpsc::Sender<T>>)>>>;
+
+/// Failure of `AsyncClient::request`, returned wrapped into `anyhow::Error`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestError {
+    /// No reply received within timeout
+    Timeout { topic: String, timeout: Duration },
+    /// Request topic has no recipients which could reply
+    NoResponder { topic: String },
+    /// Channel to orchestrator closed before reply
+    Closed { topic: String },
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::Timeout { topic, timeout } => {
+                write!(f, "request to `{}` timed out after {:?}", topic, timeout)
+            }
+            RequestError::NoResponder { topic } => {
+                write!(f, "request to `{}` has no responder", topic)
+            }
+            RequestError::Closed { topic } => {
+                write!(f, "channel closed before reply to `{}`", topic)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+/// Request received from `AsyncClient::serve` stream, answered with `AsyncClient::reply`.
+/// Synchronous responder answers with `reply` function and should call `enable_headers` first,
+/// otherwise requests arrive without correlation id and replies cannot reach requester
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub message: Message,
+    pub headers: Headers,
+}
+
+/// Request waiting for reply
+struct Waiting {
+    topic: String,
+    timeout: Duration,
+    deadline: Instant,
+    reply: oneshot::Sender<Result<Message, RequestError>>,
+}
+
+/// Requests waiting for reply by correlation id
+#[derive(Default)]
+struct Pending {
+    next: u64,
+    requests: HashMap<u64, Waiting>,
+}
+
+impl Pending {
+    /// Complete request `id` with reply built from its topic
+    fn answer(
+        &mut self,
+        id: Option<u64>,
+        reply: impl FnOnce(String) -> Result<Message, RequestError>,
+    ) {
+        if let Some(waiting) = id.and_then(|id| self.requests.remove(&id)) {
+            let _ = waiting.reply.send(reply(waiting.topic));
+        }
+    }
+}
+
+/// Async IPC client of orchestrated child process
+pub struct AsyncClient {
+    tx: Sender,
+    rx: mpsc::Receiver<Message>,
+    subscriptions: Subscriptions<Message>,
+    servers: Subscriptions<Request>,
+    pending: Arc<Mutex<Pending>>,
+    /// Deadlines of new requests for expiring thread
+    deadlines: std_mpsc::Sender<Instant>,
+}
+
+impl AsyncClient {
+    /// Connect to the IPC server passed by orchestrator in "IPC_SERVER" env var,
+    /// blocking handshake is done in a separate thread
+    pub async fn connect() -> anyhow::Result<Self> {
+        let (tx, rx) = oneshot::channel();
+        std::thread::spawn(move || tx.send(connect_ipc_server()));
+        Self::new(rx.await??)
+    }
+
+    /// Create client over connected channel, spawns receiving thread
+    /// and thread expiring requests
+    pub fn new(channel: Channel) -> anyhow::Result<Self> {
+        let (tx, ipc_rx) = channel.split()?;
+        // Headers carry correlation ids of requests and replies
+        enable_headers(&tx)?;
+        let (mut out, rx) = mpsc::channel(STREAM_CAPACITY);
+        let subscriptions = Subscriptions::default();
+        let servers = Subscriptions::default();
+        let pending = Arc::new(Mutex::new(Pending::default()));
+        let (deadlines, expiring) = std_mpsc::channel();
+        let weak = Arc::downgrade(&pending);
+        std::thread::spawn(move || expire(weak, expiring));
+
+        let (subs, servs, pend) = (subscriptions.clone(), servers.clone(), pending.clone());
+        std::thread::spawn(move || {
+            while let Ok(msg) = ipc_rx.recv() {
+                let (msg, headers) = msg.split_headers();
+                let headers = headers.unwrap_or_default();
+                let id = headers.correlation_id;
+                let msg = match msg.topic.as_str() {
+                    REPLY_TOPIC => {
+                        let data = msg.data;
+                        pend.lock()
+                            .unwrap()
+                            .answer(id, |topic| Ok(Message { topic, data }));
+                        continue;
+                    }
+                    NO_RESPONDER_TOPIC => {
+                        pend.lock()
+                            .unwrap()
+                            .answer(id, |topic| Err(RequestError::NoResponder { topic }));
+                        continue;
+                    }
+                    _ if msg.is_control() => continue,
+                    // Requests without `serve` stream are delivered as other messages
+                    _ if headers.request => {
+                        let topic = msg.topic.clone();
+                        let request = Request {
+                            message: msg,
+                            headers,
+                        };
+                        match dispatch(&servs, &topic, request) {
+                            Some(request) => request.message,
+                            None => continue,
+                        }
+                    }
+                    _ => msg,
+                };
+                let topic = msg.topic.clone();
+                if let Some(msg) = dispatch(&subs, &topic, msg) {
+                    // Client might be dropped while subscriptions are still alive
+                    block_on(out.send(msg)).unwrap_or(());
+                }
+            }
+            // Dropping waiting requests fails them with `RequestError::Closed`
+            pend.lock().unwrap().requests.clear();
+        });
+        Ok(Self {
+            tx,
+            rx,
+            subscriptions,
+            servers,
+            pending,
+            deadlines,
+        })
+    }
+
+    /// Send request with `data` to `topic` and wait for the first reply within `timeout`.
+    /// Fails with `RequestError` on timeout, when topic has no responder or channel closes.
+    /// Blocking code might wait for reply with `futures::executor::block_on`
+    pub async fn request(
+        &self,
+        topic: &str,
+        data: Vec<u8>,
+        timeout: Duration,
+    ) -> anyhow::Result<Message> {
+        let (reply, rx) = oneshot::channel();
+        let deadline = Instant::now() + timeout;
+        let id = {
+            let mut pending = self.pending.lock().unwrap();
+            pending.next += 1;
+            let id = pending.next;
+            let waiting = Waiting {
+                topic: topic.to_owned(),
+                timeout,
+                deadline,
+                reply,
+            };
+            pending.requests.insert(id, waiting);
+            id
+        };
+        let headers = Headers {
+            correlation_id: Some(id),
+            request: true,
+            ..Default::default()
+        };
+        let msg = Message {
+            topic: topic.to_owned(),
+            data,
+        };
+        if let Err(err) = send_with_headers(&self.tx, msg, &headers) {
+            self.pending.lock().unwrap().requests.remove(&id);
+            return Err(err);
+        }
+        // Expiring thread stops only when client is dropped
+        let _ = self.deadlines.send(deadline);
+        match rx.await {
+            Ok(reply) => Ok(reply?),
+            Err(_) => Err(RequestError::Closed {
+                topic: topic.to_owned(),
+            }
+            .into()),
+        }
+    }
+
+    /// Stream of requests to `topic` or pattern, each should be answered with `reply`.
+    /// Requests to topics without `serve` stream are delivered as other messages
+    pub fn serve(&self, topic: &str) -> impl Stream<Item = Request> + Unpin {
+        add_subscriber(&self.servers, topic)
+    }
+
+    /// Reply to `request` with `data`, the reply is delivered only to requester
+    pub fn reply(&self, request: &Request, data: Vec<u8>) -> anyhow::Result<()> {
+        reply(&self.tx, &request.headers, data)
+    }
+
+    /// Send message with `data` to `topic`
+    pub fn publish(&self, topic: &str, data: Vec<u8>) -> anyhow::Result<()> {
+        self.tx.send(Message {
+            topic: topic.to_owned(),
+            data,
+        })?;
+        Ok(())
+    }
+
+    /// Stream of messages to `topic` or pattern like `metrics.*`, such messages are not delivered
+    /// to the client stream. Router is asked to route `topic` to this process, see `subscribe`
+    /// function. Stream is closed by `unsubscribe`, dropping it leaves topic routed
+    /// to the client stream. Stream of invalid pattern is closed right away
+    pub fn subscribe(&self, topic: &str) -> impl Stream<Item = Message> + Unpin {
+        // Channel failure shows up on the client stream and other sends
+        let _ = subscribe(&self.tx, topic);
+        add_subscriber(&self.subscriptions, topic)
+    }
+
+    /// Ask router to stop routing `topic` to this process and close its subscription streams
+    pub fn unsubscribe(&self, topic: &str) -> anyhow::Result<()> {
+        self.subscriptions.lock().unwrap().remove(topic);
+        unsubscribe(&self.tx, topic)
+    }
+
+    /// IPC sender, e.g. to be used with `notify_ready` or `Heartbeat`
+    pub fn sender(&self) -> Sender {
+        self.tx.clone()
+    }
+}
+
+impl Stream for AsyncClient {
+    type Item = Message;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        Pin::new(&mut self.rx).poll_next(cx)
+    }
+}
+
+/// Messages are sent right away, IPC send might block while socket buffer is full
+impl Sink<Message> for AsyncClient {
+    type Error = anyhow::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<anyhow::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, msg: Message) -> anyhow::Result<()> {
+        self.tx.send(msg)?;
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<anyhow::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<anyhow::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Subscribe to `topic` or pattern, receiver of invalid pattern is closed
+fn add_subscriber<T>(subscriptions: &Subscriptions<T>, topic: &str) -> mpsc::Receiver<T> {
+    let (tx, rx) = mpsc::channel(STREAM_CAPACITY);
+    if let Ok(pattern) = TopicPattern::new(topic) {
+        subscriptions
+            .lock()
+            .unwrap()
+            .entry(topic.to_owned())
+            .or_insert_with(|| (pattern, Vec::new()))
+            .1
+            .push(tx);
+    }
+    rx
+}
+
+/// Deliver `item` to subscribers of every pattern matching `topic`,
+/// returns it back when there are none
+fn dispatch<T: Clone>(subscriptions: &Subscriptions<T>, topic: &str, item: T) -> Option<T> {
+    let subscribers: Vec<mpsc::Sender<T>> = subscriptions
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|(pattern, _)| pattern.matches(topic))
+        .flat_map(|(_, subscribers)| subscribers.iter().cloned())
+        .collect();
+    if subscribers.is_empty() {
+        return Some(item);
+    }
+    let mut closed = false;
+    for mut subscriber in subscribers {
+        closed |= block_on(subscriber.send(item.clone())).is_err();
+    }
+    if closed {
+        for (_, subscribers) in subscriptions.lock().unwrap().values_mut() {
+            subscribers.retain(|s| !s.is_closed());
+        }
+    }
+    None
+}
+
+/// Fail requests with `RequestError::Timeout` once their deadlines pass,
+/// new deadlines are received from `deadlines` until client is dropped
+fn expire(pending: Weak<Mutex<Pending>>, deadlines: std_mpsc::Receiver<Instant>) {
+    let mut next: Option<Instant> = None;
+    loop {
+        let received = match next {
+            Some(at) => deadlines.recv_timeout(at.saturating_duration_since(Instant::now())),
+            None => deadlines
+                .recv()
+                .map_err(|_| std_mpsc::RecvTimeoutError::Disconnected),
+        };
+        if let Err(std_mpsc::RecvTimeoutError::Disconnected) = received {
+            return;
+        }
+        let pending = match pending.upgrade() {
+            Some(pending) => pending,
+            None => return,
+        };
+        let mut pending = pending.lock().unwrap();
+        let now = Instant::now();
+        let expired: Vec<u64> = pending
+            .requests
+            .iter()
+            .filter(|(_, waiting)| waiting.deadline <= now)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            if let Some(waiting) = pending.requests.remove(&id) {
+                let timeout = RequestError::Timeout {
+                    topic: waiting.topic,
+                    timeout: waiting.timeout,
+                };
+                let _ = waiting.reply.send(Err(timeout));
+            }
+        }
+        next = pending
+            .requests
+            .values()
+            .map(|waiting| waiting.deadline)
+            .min();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    fn send(tx: &Sender, topic: &str) {
+        let msg = Message {
+            topic: topic.to_owned(),
+            data: vec![],
+        };
+        tx.send(msg).unwrap();
+    }
+
+    #[test]
+    fn subscription_patterns() {
+        let (client, orchestrator) = Channel::duplex().unwrap();
+        let (tx, _rx) = orchestrator.split().unwrap();
+        let mut client = AsyncClient::new(client).unwrap();
+        let mut metrics = client.subscribe("metrics.*");
+        let mut sensors = client.subscribe("sensors.#");
+        let mut invalid = client.subscribe("metrics.cpu*");
+
+        send(&tx, "metrics.cpu");
+        send(&tx, "sensors");
+        send(&tx, "sensors.room.temperature");
+        send(&tx, "metrics.cpu.load");
+        block_on(async {
+            assert_eq!(metrics.next().await.unwrap().topic, "metrics.cpu");
+            assert_eq!(sensors.next().await.unwrap().topic, "sensors");
+            let topic = sensors.next().await.unwrap().topic;
+            assert_eq!(topic, "sensors.room.temperature");
+            assert_eq!(client.next().await.unwrap().topic, "metrics.cpu.load");
+            assert!(invalid.next().await.is_none());
+        });
+
+        client.unsubscribe("metrics.*").unwrap();
+        send(&tx, "metrics.mem");
+        block_on(async {
+            assert!(metrics.next().await.is_none());
+            assert_eq!(client.next().await.unwrap().topic, "metrics.mem");
+        });
+    }
+}
@@ -0,0 +1,649 @@
+//! Declarative pipeline configuration
+//!
+//! Pipeline is described in TOML file listing processes to start
+//! and routes between their IPC bridges:
+//! - `[[route]]` forwards messages of `topic` to every bridge in `to`,
+//!   topic might be pattern like `metrics.*` or `sensors.#`, see `TopicPattern`,
+//!   routes are served by single router (`router = "crossbeam"`, `"direct"` or `"queued"`
+//!   with `[queue]` of `capacity` and `overflow` policy),
+//!   messages to topics without routes are handled according to `dead_letters` policy
+//! - `[[pipe]]` forwards every message from bridge `from` to bridge `to`
+//! - `[[link]]` connects process `from` directly to process `to` bypassing router,
+//!   processes receive their ends with `client::connect_links`, see `Orchestrator::link`
+//!
+//! Router is started when routes are configured or there are no pipes,
+//! so that processes might subscribe to topics at runtime, see `client::subscribe`.
+//!
+//! With `stats_secs` routing statistics are logged periodically,
+//! with `metrics = "127.0.0.1:9898"` they are served in Prometheus format.
+//!
+//! Processes listed in `depends_on` are started and get ready before dependent process,
+//! see `Orchestrator::start_after`.
+//! Configuration is validated before any process is started.
+//! The same file can be run with `ipc-orchestrator` binary:
+//!
+//! ```shell
+//! ipc-orchestrator pipeline.toml
+//! ```
+//!
+//! # Example
+//!
+//! ```
+//! use ipc_orchestrator::config::Config;
+//! use ipc_orchestrator::DeadLetterPolicy;
+//!
+//! let config: Config = r#"
+//!     router = "crossbeam"
+//!
+//!     [[process]]
+//!     name = "generate"
+//!     command = "cargo"
+//!     args = ["run", "--example=generate"]
+//!     env = { RUST_LOG = "info" }
+//!
+//!     [[process]]
+//!     name = "sum"
+//!     command = "cargo"
+//!     args = ["run", "--example=sum"]
+//!     restart = { policy = "on-failure", max_restarts = 3 }
+//!     depends_on = ["write"]
+//!     probes = [{ stdout = "^Connected", timeout_ms = 5000 }]
+//!     liveness = { heartbeat_ms = 1000, missed = 3, watch = [{ topic = "sum", quiet_ms = 5000 }] }
+//!
+//!     [[process]]
+//!     name = "write"
+//!     command = "cargo"
+//!     args = ["run", "--example=write"]
+//!
+//!     [[route]]
+//!     topic = "generate"
+//!     to = ["sum"]
+//!
+//!     [[pipe]]
+//!     from = "sum"
+//!     to = "write"
+//!
+//!     [[link]]
+//!     from = "generate"
+//!     to = "write"
+//! "#.parse().unwrap();
+//! config.validate().unwrap();
+//!
+//! let config: Config = r#"
+//!     router = "queued"
+//!     queue = { capacity = 100, overflow = "drop-oldest" }
+//!     dead_letters = { bridge = "logger" }
+//!
+//!     [[process]]
+//!     name = "logger"
+//!     command = "logger"
+//! "#.parse().unwrap();
+//! assert_eq!(config.dead_letters, DeadLetterPolicy::Bridge("logger".to_owned()));
+//! config.validate().unwrap();
+//!
+//! let cycle: Config = r#"
+//!     [[process]]
+//!     name = "ping"
+//!     command = "ping"
+//!     depends_on = ["pong"]
+//!
+//!     [[process]]
+//!     name = "pong"
+//!     command = "pong"
+//!     depends_on = ["ping"]
+//! "#.parse().unwrap();
+//! let err = cycle.validate().unwrap_err();
+//! assert_eq!(err.to_string(), "dependency cycle: ping -> pong -> ping");
+//! ```
+
+use crate::connected::ConnectedOrchestrator;
+use crate::dead_letter::DeadLetterPolicy;
+use crate::liveness::Liveness;
+use crate::orchestrator::orchestrator;
+use crate::probe::Probe;
+use crate::queue::Overflow;
+use crate::restart::{Restart, RestartPolicy};
+use crate::topic::TopicPattern;
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// Pipeline configuration: processes and routes between them
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Router serving `[[route]]` entries
+    #[serde(default)]
+    pub router: Router,
+    /// Recipient queues of `queued` router
+    #[serde(default)]
+    pub queue: QueueConfig,
+    /// Start child processes with RUST_BACKTRACE=1
+    #[serde(default)]
+    pub rust_backtrace: bool,
+    /// Pass child process stderr to the same log handler as stdout
+    #[serde(default)]
+    pub merge_stderr: bool,
+    /// Grace period in seconds given to processes to exit on shutdown
+    #[serde(default = "default_grace_secs")]
+    pub grace_secs: u64,
+    /// Log routing statistics every `stats_secs` seconds
+    #[serde(default)]
+    pub stats_secs: Option<u64>,
+    /// Address of Prometheus metrics endpoint, e.g. "127.0.0.1:9898", requires `metrics` feature
+    #[serde(default)]
+    pub metrics: Option<String>,
+    #[serde(default, rename = "process")]
+    pub processes: Vec<ProcessConfig>,
+    #[serde(default, rename = "route")]
+    pub routes: Vec<RouteConfig>,
+    #[serde(default, rename = "pipe")]
+    pub pipes: Vec<PipeConfig>,
+    #[serde(default, rename = "link")]
+    pub links: Vec<LinkConfig>,
+    /// What router does with messages to topics without recipients:
+    /// `"fail"`, `"drop"`, `"log"` (default), `{ bridge = "name" }` or `{ topic = "name" }`
+    #[serde(default)]
+    pub dead_letters: DeadLetterPolicy,
+}
+
+/// Router implementation, see `ConnectedOrchestrator::pipe_routes`,
+/// `ConnectedOrchestrator::pipe_routes_via_crossbeam`
+/// and `ConnectedOrchestrator::pipe_routes_via_queues`
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Router {
+    Direct,
+    #[default]
+    Crossbeam,
+    /// Bounded queue per recipient configured with `[queue]`
+    Queued,
+}
+
+/// Recipient queues of `queued` router
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QueueConfig {
+    /// Messages queue of every recipient can hold
+    #[serde(default = "default_queue_capacity")]
+    pub capacity: usize,
+    /// What router does when queue is full
+    #[serde(default)]
+    pub overflow: Overflow,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: default_queue_capacity(),
+            overflow: Overflow::default(),
+        }
+    }
+}
+
+fn default_queue_capacity() -> usize {
+    1024
+}
+
+/// Process to start
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProcessConfig {
+    pub name: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Working directory, relative to orchestrator's working directory
+    pub cwd: Option<PathBuf>,
+    /// Connect process via IPC, on by default
+    #[serde(default = "default_ipc")]
+    pub ipc: bool,
+    #[serde(default)]
+    pub restart: RestartConfig,
+    /// Processes which have to be ready before this process starts
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Readiness probes
+    #[serde(default)]
+    pub probes: Vec<ProbeConfig>,
+    pub liveness: Option<LivenessConfig>,
+}
+
+/// Liveness conditions of a process, see `Liveness`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LivenessConfig {
+    /// Expected heartbeat interval
+    pub heartbeat_ms: Option<u64>,
+    /// Number of missed heartbeat intervals after which process is unhealthy
+    #[serde(default = "default_missed_heartbeats")]
+    pub missed: u32,
+    #[serde(default)]
+    pub watch: Vec<WatchConfig>,
+}
+
+/// Process should send messages to `topic` at least once per `quiet_ms`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WatchConfig {
+    pub topic: String,
+    pub quiet_ms: u64,
+}
+
+/// Readiness probe, exactly one of `stdout`, `tcp`, `file` or `ready_message` should be set,
+/// see `Probe`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProbeConfig {
+    /// Regex matching stdout line
+    pub stdout: Option<String>,
+    /// Address accepting TCP connections
+    pub tcp: Option<String>,
+    /// File which should appear
+    pub file: Option<PathBuf>,
+    /// Process sends ready message via IPC
+    #[serde(default)]
+    pub ready_message: bool,
+    #[serde(default = "default_probe_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+/// Restart policy of a process, see `RestartPolicy`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RestartConfig {
+    #[serde(default = "default_restart")]
+    pub policy: Restart,
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    #[serde(default = "default_max_restarts")]
+    pub max_restarts: usize,
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+}
+
+impl Default for RestartConfig {
+    fn default() -> Self {
+        Self {
+            policy: default_restart(),
+            initial_backoff_ms: default_initial_backoff_ms(),
+            max_backoff_ms: default_max_backoff_ms(),
+            max_restarts: default_max_restarts(),
+            window_secs: default_window_secs(),
+        }
+    }
+}
+
+/// Messages of `topic` are delivered to every bridge listed in `to`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    pub topic: String,
+    pub to: Vec<String>,
+}
+
+/// All the messages from bridge `from` are delivered to bridge `to`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipeConfig {
+    pub from: String,
+    pub to: String,
+}
+
+/// Process `from` sends messages directly to process `to`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LinkConfig {
+    pub from: String,
+    pub to: String,
+}
+
+fn default_grace_secs() -> u64 {
+    5
+}
+fn default_ipc() -> bool {
+    true
+}
+fn default_restart() -> Restart {
+    Restart::Never
+}
+fn default_initial_backoff_ms() -> u64 {
+    100
+}
+fn default_max_backoff_ms() -> u64 {
+    30_000
+}
+fn default_max_restarts() -> usize {
+    5
+}
+fn default_window_secs() -> u64 {
+    60
+}
+fn default_probe_timeout_ms() -> u64 {
+    30_000
+}
+fn default_missed_heartbeats() -> u32 {
+    3
+}
+
+impl FromStr for Config {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        toml::from_str(s).context("failed to parse pipeline config")
+    }
+}
+
+impl Config {
+    /// Read configuration from TOML file
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?
+            .parse()
+            .with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    /// Grace period given to processes to exit on shutdown
+    pub fn grace(&self) -> Duration {
+        Duration::from_secs(self.grace_secs)
+    }
+
+    /// Check that processes are unique and routes refer to IPC enabled processes
+    ///
+    /// Bridge can be destination of several routes or of only one pipe
+    /// and source of only one pipe.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.processes.is_empty() {
+            return Err(anyhow!("no processes configured"));
+        }
+        #[cfg(not(feature = "metrics"))]
+        if let Some(addr) = self.metrics.as_ref() {
+            return Err(anyhow!(
+                "metrics endpoint {} requires `metrics` feature",
+                addr
+            ));
+        }
+        if self.router == Router::Queued && self.queue.capacity == 0 {
+            return Err(anyhow!("queue capacity should be positive"));
+        }
+        let mut ipc = HashSet::new();
+        let mut names = HashSet::new();
+        for process in self.processes.iter() {
+            if process.name.is_empty() {
+                return Err(anyhow!("process name should not be empty"));
+            }
+            if process.command.is_empty() {
+                return Err(anyhow!("process `{}` has empty command", process.name));
+            }
+            if !names.insert(process.name.as_str()) {
+                return Err(anyhow!("process `{}` configured twice", process.name));
+            }
+            if process.ipc {
+                ipc.insert(process.name.as_str());
+            }
+            for probe in process.probes.iter() {
+                probe
+                    .probe()
+                    .with_context(|| format!("invalid probe of `{}`", process.name))?;
+                if probe.ready_message && !process.ipc {
+                    return Err(anyhow!(
+                        "process `{}` should use IPC to send ready message",
+                        process.name
+                    ));
+                }
+            }
+            if process.liveness.is_some() && !process.ipc {
+                return Err(anyhow!(
+                    "process `{}` should use IPC for liveness monitoring",
+                    process.name
+                ));
+            }
+        }
+
+        self.start_order()?;
+
+        let bridge = |name: &str, role: &str| {
+            if ipc.contains(name) {
+                Ok(())
+            } else if names.contains(name) {
+                Err(anyhow!("{} `{}` is not connected via ipc", role, name))
+            } else {
+                Err(anyhow!("{} `{}` is not configured", role, name))
+            }
+        };
+        let mut destinations = HashSet::new();
+        let mut sources = HashSet::new();
+        for route in self.routes.iter() {
+            TopicPattern::new(&route.topic).context("invalid route")?;
+            if route.to.is_empty() {
+                return Err(anyhow!(
+                    "route of topic `{}` has no destinations",
+                    route.topic
+                ));
+            }
+            for to in route.to.iter() {
+                bridge(to, "route destination")?;
+                destinations.insert(to.as_str());
+            }
+        }
+        match &self.dead_letters {
+            DeadLetterPolicy::Bridge(name) => {
+                bridge(name, "dead letters bridge")?;
+                destinations.insert(name.as_str());
+            }
+            DeadLetterPolicy::Topic(topic) if !TopicPattern::new(topic)?.is_exact() => {
+                return Err(anyhow!("dead letters topic `{}` is a pattern", topic));
+            }
+            _ => {}
+        }
+        for pipe in self.pipes.iter() {
+            bridge(&pipe.from, "pipe source")?;
+            bridge(&pipe.to, "pipe destination")?;
+            if !sources.insert(pipe.from.as_str()) {
+                return Err(anyhow!("bridge `{}` is source of several pipes", pipe.from));
+            }
+            if !destinations.insert(pipe.to.as_str()) {
+                return Err(anyhow!(
+                    "bridge `{}` is destination of a pipe and another route or pipe",
+                    pipe.to
+                ));
+            }
+        }
+        let mut links = HashSet::new();
+        for link in self.links.iter() {
+            for name in [&link.from, &link.to].iter() {
+                if !names.contains(name.as_str()) {
+                    return Err(anyhow!("linked process `{}` is not configured", name));
+                }
+            }
+            if link.from == link.to {
+                return Err(anyhow!("process `{}` is linked to itself", link.from));
+            }
+            if !links.insert((link.from.as_str(), link.to.as_str())) {
+                return Err(anyhow!("`{}` is linked to `{}` twice", link.from, link.to));
+            }
+            let to = self.processes.iter().find(|p| p.name == link.to).unwrap();
+            if to.restart.policy != Restart::Never {
+                return Err(anyhow!(
+                    "process `{}` receives peer links, it cannot be restarted",
+                    link.to
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Validate configuration, start processes, connect to them and set up routes
+    ///
+    /// Resulting ConnectedOrchestrator is ready to `run()`
+    pub async fn connect(&self) -> anyhow::Result<ConnectedOrchestrator> {
+        self.validate()?;
+        let mut orchestrator = orchestrator()
+            .rust_backtrace(self.rust_backtrace)
+            .merge_stderr(self.merge_stderr);
+        for link in self.links.iter() {
+            orchestrator.link(&link.from, &link.to)?;
+        }
+        for process in self.start_order()? {
+            for probe in process.probes.iter() {
+                orchestrator.probe(&process.name, probe.probe()?)?;
+            }
+            if let Some(liveness) = process.liveness.as_ref() {
+                orchestrator.liveness(&process.name, liveness.liveness())?;
+            }
+            orchestrator = orchestrator.ipc(process.ipc);
+            let cmd = process.command();
+            let policy = process.restart.policy();
+            if process.depends_on.is_empty() {
+                orchestrator.start_with_policy(&process.name, cmd, policy)
+            } else {
+                let deps: Vec<&str> = process.depends_on.iter().map(String::as_str).collect();
+                orchestrator.start_after(&process.name, cmd, policy, &deps)
+            }
+            .with_context(|| format!("failed to start `{}`", process.name))?;
+        }
+
+        let mut orchestra = orchestrator.connect().await?;
+        // Pipes go first, so their sources are not taken by router
+        for pipe in self.pipes.iter() {
+            orchestra.pipe_bridges(&pipe.from, &pipe.to)?;
+        }
+        if !self.routes.is_empty() || self.pipes.is_empty() {
+            for route in self.routes.iter() {
+                for to in route.to.iter() {
+                    orchestra.route_topic_to_bridge(&route.topic, to)?;
+                }
+            }
+            orchestra.dead_letters_policy(self.dead_letters.clone())?;
+            match self.router {
+                Router::Direct => orchestra.pipe_routes()?,
+                Router::Crossbeam => orchestra.pipe_routes_via_crossbeam()?,
+                Router::Queued => {
+                    orchestra.pipe_routes_via_queues(self.queue.capacity, self.queue.overflow)?
+                }
+            }
+        }
+        if let Some(secs) = self.stats_secs {
+            orchestra.log_stats(Duration::from_secs(secs));
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(addr) = self.metrics.as_ref() {
+            orchestra.serve_metrics(addr)?;
+        }
+        Ok(orchestra)
+    }
+}
+
+impl Config {
+    /// Processes ordered so that dependencies go before dependent processes,
+    /// fails on unknown dependencies and dependency cycles
+    fn start_order(&self) -> anyhow::Result<Vec<&ProcessConfig>> {
+        let processes: HashMap<&str, &ProcessConfig> = self
+            .processes
+            .iter()
+            .map(|process| (process.name.as_str(), process))
+            .collect();
+        let mut order = Vec::with_capacity(self.processes.len());
+        let mut visited = HashSet::new();
+        // Depth first search keeping current path to report cycles
+        let mut path: Vec<&str> = Vec::new();
+        fn visit<'a>(
+            process: &'a ProcessConfig,
+            processes: &HashMap<&str, &'a ProcessConfig>,
+            visited: &mut HashSet<&'a str>,
+            path: &mut Vec<&'a str>,
+            order: &mut Vec<&'a ProcessConfig>,
+        ) -> anyhow::Result<()> {
+            let name = process.name.as_str();
+            if let Some(pos) = path.iter().position(|p| *p == name) {
+                let mut cycle = path[pos..].to_vec();
+                cycle.push(name);
+                return Err(anyhow!("dependency cycle: {}", cycle.join(" -> ")));
+            }
+            if visited.contains(name) {
+                return Ok(());
+            }
+            path.push(name);
+            for dep in process.depends_on.iter() {
+                let dep = processes.get(dep.as_str()).ok_or_else(|| {
+                    anyhow!("process `{}` depends on unknown process `{}`", name, dep)
+                })?;
+                visit(dep, processes, visited, path, order)?;
+            }
+            path.pop();
+            visited.insert(name);
+            order.push(process);
+            Ok(())
+        }
+        for process in self.processes.iter() {
+            visit(process, &processes, &mut visited, &mut path, &mut order)?;
+        }
+        Ok(order)
+    }
+}
+
+impl ProcessConfig {
+    /// Command to start process
+    pub fn command(&self) -> Command {
+        let mut cmd = Command::new(&self.command);
+        cmd.args(&self.args).envs(&self.env);
+        if let Some(cwd) = self.cwd.as_ref() {
+            cmd.current_dir(cwd);
+        }
+        cmd
+    }
+}
+
+impl ProbeConfig {
+    pub fn probe(&self) -> anyhow::Result<Probe> {
+        let probe = match (&self.stdout, &self.tcp, &self.file, self.ready_message) {
+            (Some(pattern), None, None, false) => Probe::output_line(pattern)?,
+            (None, Some(addr), None, false) => Probe::tcp_port(addr),
+            (None, None, Some(path), false) => Probe::file(path),
+            (None, None, None, true) => Probe::ready_message(),
+            _ => {
+                return Err(anyhow!(
+                    "probe should have exactly one of stdout, tcp, file or ready_message"
+                ))
+            }
+        };
+        Ok(probe.timeout(Duration::from_millis(self.timeout_ms)))
+    }
+}
+
+impl LivenessConfig {
+    pub fn liveness(&self) -> Liveness {
+        let liveness = match self.heartbeat_ms {
+            Some(ms) => Liveness::heartbeat(Duration::from_millis(ms), self.missed),
+            None => Liveness::default(),
+        };
+        self.watch.iter().fold(liveness, |liveness, watch| {
+            liveness.watch_topic(&watch.topic, Duration::from_millis(watch.quiet_ms))
+        })
+    }
+}
+
+impl RestartConfig {
+    pub fn policy(&self) -> RestartPolicy {
+        let policy = match self.policy {
+            Restart::Never => RestartPolicy::never(),
+            Restart::OnFailure => RestartPolicy::on_failure(),
+            Restart::Always => RestartPolicy::always(),
+        };
+        policy
+            .backoff(
+                Duration::from_millis(self.initial_backoff_ms),
+                Duration::from_millis(self.max_backoff_ms),
+            )
+            .max_restarts(self.max_restarts, Duration::from_secs(self.window_secs))
+    }
+}
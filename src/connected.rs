@@ -1,42 +1,528 @@
-use crate::message::Message;
-use crate::{may_complete, never_fail, should_not_complete};
-use crate::{Bridge, Receiver, Sender};
-use anyhow::anyhow;
+use crate::capture::{self, CaptureReader, CaptureWriter, Pace, Record};
+use crate::dead_letter::{DeadLetterPolicy, DeadLetters};
+use crate::error::OrchestratorError;
+use crate::message::{Headers, Message};
+use crate::orchestrator::{Ready, Spawned, Spawner, BFR};
+use crate::queue::{Overflow, Queue, QueueStats};
+use crate::restart::{BridgeRx, BridgeTx, LiveStates, ProcessState, RestartPolicy, Stamp};
+use crate::routes::{Output, RouteChanges, Routes, Tap};
+use crate::should_not_complete;
+use crate::shutdown::{signal, ExitReport, Stop};
+use crate::stats::{Counter, Registry, Stats};
+use crate::topic::TopicPattern;
+use crate::{Bridge, Channel, Receiver};
+use anyhow::{anyhow, Context};
 use crossbeam::channel;
-use futures::future::{try_join_all, FusedFuture, FutureExt};
+use futures::channel::{mpsc, oneshot};
+use futures::future::{Fuse, Future, FutureExt};
+use futures::stream::{FuturesUnordered, Stream, StreamExt};
 use futures::{pin_mut, select};
-use ipc_channel::ipc::{IpcReceiverSet, IpcSelectionResult};
-use log::{error, info, trace};
-use std::collections::HashMap;
+use ipc_channel::ipc::{self, IpcReceiverSet, IpcSelectionResult, IpcSender};
+use log::{error, info, trace, warn};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 use std::pin::Pin;
+use std::process::ExitStatus;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::process::Command;
 use tokio::task::JoinHandle;
 
-type TryAllPin = Pin<Box<dyn FusedFuture<Output = anyhow::Result<Vec<()>>>>>;
+type Reply<T> = oneshot::Sender<anyhow::Result<T>>;
+type Joining = Pin<Box<dyn Future<Output = (String, anyhow::Result<Ready>, Reply<()>)>>>;
+
+/// How often process exit is checked during shutdown
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 /// Orchestrator with successfully started processes connected via IPC
-pub struct ConnectedOrchestrator<LF: FusedFuture> {
+pub struct ConnectedOrchestrator {
     pub bridges: HashMap<String, Bridge>,
-    routes: Option<HashMap<String, Vec<Sender>>>,
-    pipes: Vec<JoinHandle<anyhow::Result<()>>>,
-    loggers: Pin<Box<LF>>,
-    processes: TryAllPin,
+    routes: Option<Routes>,
+    /// Traffic counters, dead letters and recipient queues of routing threads
+    stats: Registry,
+    pipes: FuturesUnordered<JoinHandle<anyhow::Result<()>>>,
+    processes: FuturesUnordered<BFR<()>>,
+    /// Failure of process while orchestrator waited for another one to spawn or exit,
+    /// reported by `run`
+    failed: Option<anyhow::Error>,
+    states: HashMap<String, Arc<ProcessState>>,
+    /// State of in-process participants by bridge name, see `participant`
+    participants: HashMap<String, Arc<ProcessState>>,
+    /// States of processes and participants as they are spawned and stopped
+    live: LiveStates,
+    /// Processes which every process depends on
+    dependencies: HashMap<String, Vec<String>>,
+    /// Direct links between processes, see `Orchestrator::link`
+    links: Vec<(String, String)>,
+    spawner: Box<dyn Spawner>,
+    /// Processes spawned via `OrchestratorHandle` which are getting ready
+    joining: FuturesUnordered<Joining>,
+    control: (
+        mpsc::UnboundedSender<Control>,
+        mpsc::UnboundedReceiver<Control>,
+    ),
+    /// Router started with one of `pipe_routes` methods
+    router: Option<RouterLink>,
+    stop_tx: Option<channel::Sender<()>>,
+    stop: Stop,
+}
+
+/// Handle to spawn and stop processes while orchestrator is running,
+/// see `ConnectedOrchestrator::handle`
+#[derive(Clone)]
+pub struct OrchestratorHandle(mpsc::UnboundedSender<Control>);
+
+/// Request of `OrchestratorHandle`, served by running orchestrator
+enum Control {
+    Spawn {
+        name: String,
+        cmd: Box<Command>,
+        policy: RestartPolicy,
+        done: Reply<()>,
+    },
+    Stop {
+        name: String,
+        grace: Duration,
+        done: Reply<Option<ExitStatus>>,
+    },
+}
+
+/// What running orchestrator reacts to
+enum Event {
+    Channels(anyhow::Result<()>),
+    Process(anyhow::Result<()>),
+    Joined(String, anyhow::Result<Ready>, Reply<()>),
+    Control(Control),
+}
+
+/// Handle to change routes of running router, see `ConnectedOrchestrator::route_table`
+#[derive(Clone)]
+pub struct RouteTable(Updates);
+
+/// Running router, which bridges of spawned and stopped processes join and leave
+struct RouterLink {
+    updates: Updates,
+    /// Channel to crossbeam router from threads receiving from bridges
+    feed: Option<channel::Sender<(Message, Headers)>>,
+    /// Capacity and overflow policy of recipient queues
+    queues: Option<(usize, Overflow)>,
+}
+
+/// Change of bridges connected to running router or of its routes
+enum Update {
+    /// Bridge starts receiving from router, its receiver is taken by router
+    /// unless threads receive from bridges
+    Join(Option<BridgeRx>, Output),
+    /// Bridge with all its routes is removed from router, which confirms it's done
+    Leave(String, oneshot::Sender<()>),
+    /// Routes are changed at once
+    Routes(RouteChanges, Reply<()>),
+    /// Messages of any of topics are observed
+    Tap(Vec<TopicPattern>, Tap),
+    /// Message is delivered as if its source bridge sent it
+    Inject(Message, Headers),
+    /// Message is sent to bridge regardless of routes
+    SendTo(String, Message, Headers),
+}
+
+/// Sender of updates to running router
+#[derive(Clone)]
+struct Updates {
+    tx: channel::Sender<Update>,
+    /// Wakes up router selecting IPC channels, crossbeam router has none
+    doorbell: Option<IpcSender<bool>>,
+    /// Processes and participants messages might be sent to
+    live: LiveStates,
+}
+
+impl Updates {
+    fn send(&self, update: Update) -> anyhow::Result<()> {
+        let closed = || OrchestratorError::RouterClosed {
+            reason: "router stopped".to_owned(),
+        };
+        self.tx.send(update).map_err(|_| closed())?;
+        if let Some(doorbell) = self.doorbell.as_ref() {
+            doorbell.send(true).map_err(|_| closed())?;
+        }
+        Ok(())
+    }
+
+    fn publish(&self, topic: &str, data: Vec<u8>) -> anyhow::Result<()> {
+        let msg = host_message(topic, data)?;
+        self.send(Update::Inject(msg, host_headers()))
+    }
+
+    fn send_to(&self, b_out: &str, message: Message) -> anyhow::Result<()> {
+        let connected = self
+            .live
+            .read()
+            .unwrap()
+            .get(b_out)
+            .is_some_and(|state| state.connected.load(Ordering::Relaxed));
+        if !connected {
+            return Err(anyhow!("bridge `{}` not found", b_out));
+        }
+        let msg = host_message(&message.topic, message.data)?;
+        self.send(Update::SendTo(b_out.to_owned(), msg, host_headers()))
+    }
 }
 
-impl<LF> ConnectedOrchestrator<LF>
-where
-    LF: FusedFuture<Output = anyhow::Result<Vec<()>>>,
-{
-    pub(crate) fn new(bridges: Vec<Bridge>, processes: TryAllPin, loggers: Pin<Box<LF>>) -> Self {
+/// Message of host code, control topics are reserved for orchestrator
+fn host_message(topic: &str, data: Vec<u8>) -> anyhow::Result<Message> {
+    let msg = Message {
+        topic: topic.to_owned(),
+        data,
+    };
+    if msg.is_control() {
+        return Err(anyhow!(
+            "topic `{}` is reserved for control messages",
+            topic
+        ));
+    }
+    Ok(msg)
+}
+
+/// Headers of message published by host code, which has no source bridge
+fn host_headers() -> Headers {
+    Headers {
+        timestamp: Some(SystemTime::now()),
+        ..Default::default()
+    }
+}
+
+/// Headers of message sent by process `name` before it joined router
+fn process_headers(name: &str) -> Headers {
+    Headers {
+        source: Some(name.to_owned()),
+        ..host_headers()
+    }
+}
+
+impl Update {
+    /// Change routes, returns receiver of joining bridge
+    fn apply(
+        self,
+        routes: &mut Routes,
+        stop: &Stop,
+    ) -> Result<Option<BridgeRx>, OrchestratorError> {
+        match self {
+            Update::Join(rx, output) => {
+                routes.join(output);
+                return Ok(rx);
+            }
+            Update::Leave(name, done) => {
+                routes.leave(&name);
+                let _ = done.send(());
+            }
+            Update::Routes(changes, done) => {
+                let _ = done.send(routes.apply(changes));
+            }
+            Update::Tap(topics, tap) => routes.tap(topics, tap),
+            Update::Inject(msg, headers) => routes.deliver(msg, &headers, stop)?,
+            Update::SendTo(name, msg, headers) => routes.send_to(&name, msg, &headers, stop)?,
+        }
+        Ok(None)
+    }
+}
+
+/// IPC channels which router receives from, with state of their bridges
+struct Receivers {
+    set: IpcReceiverSet,
+    names: HashMap<u64, String>,
+    /// Receivers of channels from restarted processes
+    reconnects: HashMap<u64, String>,
+    /// Channels of bridges which left router, they are expected to close
+    left: HashSet<u64>,
+    states: HashMap<String, Arc<ProcessState>>,
+    sources: HashMap<String, Arc<Counter>>,
+    stamps: HashMap<String, Stamp>,
+}
+
+impl Receivers {
+    fn add(&mut self, rx: BridgeRx) -> anyhow::Result<()> {
+        let name = rx.name.clone();
+        info!("setting up receiver {}", name);
+        let (recv, feed, state, traffic) = rx.into_parts();
+        if let Some(state) = state {
+            self.states.insert(name.clone(), state);
+        }
+        self.sources.insert(name.clone(), traffic);
+        self.stamps.insert(name.clone(), Stamp::new(&name));
+        let id = self.set.add(recv)?;
+        self.names.insert(id, name.clone());
+        if let Some(feed) = feed {
+            let id = self.set.add(feed)?;
+            self.reconnects.insert(id, name);
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self, name: &str) {
+        let ids = self.names.iter().chain(self.reconnects.iter());
+        let left = ids.filter(|(_, n)| *n == name).map(|(id, _)| *id);
+        self.left.extend(left);
+    }
+}
+
+impl ConnectedOrchestrator {
+    pub(crate) fn new(
+        bridges: Vec<Bridge>,
+        processes: FuturesUnordered<BFR<()>>,
+        states: HashMap<String, Arc<ProcessState>>,
+        dependencies: HashMap<String, Vec<String>>,
+        links: Vec<(String, String)>,
+        (stop_tx, stop): (channel::Sender<()>, Stop),
+        spawner: Box<dyn Spawner>,
+    ) -> Self {
+        let stats = Registry::default();
+        let live = Arc::new(RwLock::new(states.clone()));
         ConnectedOrchestrator {
             bridges: bridges
                 .into_iter()
                 .map(|bridge| (bridge.name.clone(), bridge))
                 .collect(),
-            routes: Some(HashMap::new()),
+            routes: Some(Routes::new(&stats)),
+            stats,
             processes,
-            loggers,
-            pipes: Vec::new(),
+            failed: None,
+            pipes: FuturesUnordered::new(),
+            states,
+            participants: HashMap::new(),
+            live,
+            dependencies,
+            links,
+            spawner,
+            joining: FuturesUnordered::new(),
+            control: mpsc::unbounded(),
+            router: None,
+            stop_tx: Some(stop_tx),
+            stop,
+        }
+    }
+
+    /// Number of times process `name` was restarted according to its restart policy
+    pub fn restarts(&self, name: &str) -> Option<usize> {
+        self.states
+            .get(name)
+            .map(|state| state.restarts.load(Ordering::Relaxed))
+    }
+
+    /// Direct links between processes as `(from, to)` pairs, see `Orchestrator::link`.
+    /// Messages sent over links bypass router
+    pub fn links(&self) -> &[(String, String)] {
+        &self.links
+    }
+
+    /// Handle to spawn and stop processes from other tasks while orchestrator is running,
+    /// requests are served by `run` and `run_until`
+    pub fn handle(&self) -> OrchestratorHandle {
+        OrchestratorHandle(self.control.0.clone())
+    }
+
+    /// Start process `name` after orchestrator connected, see `spawn_with_policy`
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use tokio::process::Command;
+    /// use ipc_orchestrator::orchestrator;
+    ///
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let mut orchestrator = orchestrator().ipc(false);
+    ///     orchestrator.start("first", Command::new("sleep").arg("100")).unwrap();
+    ///     let mut orchestra = orchestrator.connect().await.unwrap();
+    ///     let mut cmd = Command::new("sleep");
+    ///     cmd.arg("100");
+    ///     orchestra.spawn("second", cmd).await.unwrap();
+    ///     let status = orchestra.stop("second", Duration::from_secs(1)).await.unwrap();
+    ///     assert!(!status.unwrap().success());
+    ///     assert!(orchestra.spawn("first", Command::new("true")).await.is_err());
+    /// # });
+    /// ```
+    pub async fn spawn(&mut self, name: &str, cmd: Command) -> anyhow::Result<()> {
+        self.spawn_with_policy(name, cmd, RestartPolicy::never())
+            .await
+    }
+
+    /// Start process `name` same way as `Orchestrator::start_with_policy` does
+    /// and wait for it to connect. Process which failed to connect is killed.
+    /// Failure of another process meanwhile is kept to be reported by `run`.
+    ///
+    /// If router is running, bridge of process joins it: process is not routed from any topic,
+    /// though it might subscribe to topics, send messages and requests.
+    /// Otherwise its bridge is added to `bridges`
+    pub async fn spawn_with_policy(
+        &mut self,
+        name: &str,
+        cmd: Command,
+        policy: RestartPolicy,
+    ) -> anyhow::Result<()> {
+        let ready = self.start(name, cmd, policy)?.fuse();
+        pin_mut!(ready);
+        let ready = loop {
+            select!(
+                ready = ready => break ready,
+                res = self.processes.select_next_some() => {
+                    // Another process failed meanwhile, it is reported by `run`
+                    if let Err(err) = res {
+                        error!("processes failure: {}", err);
+                        self.failed.get_or_insert(err);
+                    }
+                },
+            )
+        };
+        self.join(name, ready)
+    }
+
+    /// Stop process `name` while orchestrator is running: its bridge leaves router,
+    /// then process receives SIGTERM, and SIGKILL if it did not exit within `grace` period.
+    ///
+    /// Stopped process is not restarted, pipes of its bridge complete.
+    /// Its name might be used to spawn process again. Returns exit status of process,
+    /// which is None for in-process participant, see `participant`
+    pub async fn stop(
+        &mut self,
+        name: &str,
+        grace: Duration,
+    ) -> anyhow::Result<Option<ExitStatus>> {
+        let state = self
+            .state(name)
+            .ok_or_else(|| anyhow!("process `{}` not found", name))?;
+        if state.stopped.swap(true, Ordering::Relaxed) {
+            return Err(anyhow!("process `{}` is already stopped", name));
+        }
+        info!("stopping {}", name);
+        if let Some(router) = self.router.as_ref() {
+            // Router should not send to process which is exiting
+            let (done, left) = oneshot::channel();
+            router.updates.send(Update::Leave(name.to_owned(), done))?;
+            if tokio::time::timeout(grace, left).await.is_err() {
+                warn!("router did not remove `{}` within {:?}", name, grace);
+            }
+        }
+        self.bridges.remove(name);
+        self.stats.remove_queue(name);
+        for (from, to) in self.links.iter().filter(|(f, t)| f == name || t == name) {
+            warn!("peer link {} -> {} is down, `{}` stopped", from, to, name);
+        }
+        // Participant returns once its channel closes
+        if self.participants.remove(name).is_some() {
+            self.live.write().unwrap().remove(name);
+            return Ok(None);
         }
+
+        let names = [name.to_owned()];
+        signal(state.pid.load(Ordering::Relaxed), libc::SIGTERM);
+        if !self.wait_exited(&names, grace).await {
+            if signal(state.pid.load(Ordering::Relaxed), libc::SIGKILL) {
+                warn!("process `{}` did not exit within {:?}, killed", name, grace);
+            }
+            self.wait_exited(&names, grace).await;
+        }
+        let status = *state.exit.lock().unwrap();
+        info!("{} stopped: {:?}", name, status);
+        Ok(status)
+    }
+
+    /// Register in-process participant under bridge `name`, returns its end of IPC channel.
+    /// Participant is piped and routed as process, e.g. it might be wrapped into `AsyncClient`
+    /// to publish, subscribe and serve requests from async task.
+    /// If router is running, participant joins it same way as spawned process does.
+    ///
+    /// Participant leaves router when it is stopped with `stop` and on shutdown,
+    /// then its receiver disconnects and participant should drop its channel.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use ipc_orchestrator::{orchestrator, message::Message};
+    ///
+    /// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+    ///     let mut orchestra = orchestrator().ipc(true).connect().await.unwrap();
+    ///     let (numbers, doubled) = orchestra.participant("numbers").unwrap().split().unwrap();
+    ///     orchestra
+    ///         .spawn_transform("double", |msg| {
+    ///             let data = msg.data.iter().map(|x| x * 2).collect();
+    ///             Some(Message { topic: "doubled".to_owned(), data })
+    ///         })
+    ///         .unwrap();
+    ///     orchestra.route_topic_to_bridge("numbers", "double").unwrap();
+    ///     orchestra.route_topic_to_bridge("doubled", "numbers").unwrap();
+    ///     orchestra.pipe_routes().unwrap();
+    ///     numbers.send(Message { topic: "numbers".to_owned(), data: vec![1, 2] }).unwrap();
+    ///     assert_eq!(doubled.recv().unwrap().data, vec![2, 4]);
+    ///     // Participant drops its channel once it left router
+    ///     orchestra.stop("numbers", Duration::from_secs(1)).await.unwrap();
+    ///     drop(numbers);
+    ///     let report = orchestra.shutdown(Duration::from_secs(1)).await.unwrap();
+    ///     assert!(report.errors.is_empty());
+    /// # });
+    /// ```
+    pub fn participant(&mut self, name: &str) -> anyhow::Result<Channel> {
+        let taken = self.bridges.contains_key(name)
+            || self.participants.contains_key(name)
+            || self
+                .states
+                .get(name)
+                .is_some_and(|state| !state.stopped.load(Ordering::Relaxed) || state.is_running());
+        if taken {
+            return Err(anyhow!("bridge named `{}` already exists", name));
+        }
+        info!("adding in-process participant {}", name);
+        let (channel, participant) = Channel::duplex()?;
+        let state = ProcessState {
+            in_process: true,
+            ..ProcessState::new(None)
+        };
+        state.connected.store(true, Ordering::Relaxed);
+        let state = Arc::new(state);
+        self.participants.insert(name.to_owned(), state.clone());
+        self.live.write().unwrap().insert(name.to_owned(), state);
+        self.bridges.insert(
+            name.to_owned(),
+            Bridge {
+                channel,
+                name: name.to_owned(),
+                tx_reconnects: None,
+                rx_reconnects: None,
+            },
+        );
+        self.join_router(name)?;
+        Ok(participant)
+    }
+
+    /// Run `participant` with its channel in a tokio blocking task thread,
+    /// see `participant`. Participant failure is reported as failure of routing threads
+    pub fn spawn_participant<F>(&mut self, name: &str, participant: F) -> anyhow::Result<()>
+    where
+        F: FnOnce(Channel) -> anyhow::Result<()> + Send + 'static,
+    {
+        let channel = self.participant(name)?;
+        let name = name.to_owned();
+        let handle = tokio::task::spawn_blocking(move || {
+            participant(channel).with_context(|| format!("participant `{}` failed", name))
+        });
+        self.pipes.push(handle);
+        Ok(())
+    }
+
+    /// Run in-process participant `name` passing every message routed to it to `transform`,
+    /// message it returns is published. Participant returns once it leaves router
+    pub fn spawn_transform<F>(&mut self, name: &str, mut transform: F) -> anyhow::Result<()>
+    where
+        F: FnMut(Message) -> Option<Message> + Send + 'static,
+    {
+        self.spawn_participant(name, move |channel| {
+            let (tx, rx) = channel.split()?;
+            while let Ok(msg) = rx.recv() {
+                if msg.is_control() {
+                    continue;
+                }
+                if let Some(msg) = transform(msg) {
+                    tx.send(msg)?;
+                }
+            }
+            Ok(())
+        })
     }
 
     /// Build a pipe from modules b_in to b_out
@@ -45,15 +531,27 @@ where
     /// - b_out name of outgoing bridge from Self::bridges
     pub fn pipe_bridges(&mut self, b_in: &str, b_out: &str) -> anyhow::Result<()> {
         info!("setting communication {} -> {}", b_in, b_out);
-        let rx: Receiver = self.take_bridge_rx(b_in)?;
-        let tx: Sender = self.take_bridge_tx(b_out)?;
-        let (b_in, b_out) = (b_in.to_owned(), b_out.to_owned());
+        let mut rx = self.take_bridge_rx(b_in)?;
+        let mut tx = self.take_bridge_tx(b_out)?;
+        let b_out = b_out.to_owned();
+        let stop = self.stop.clone();
+        let mut topics = self.stats.topics();
         let handle = tokio::task::spawn_blocking(move || loop {
-            let buf: Message = rx
-                .recv()
-                .unwrap_or_else(|err| todo!("receiving message from {} failed: {}", b_in, err));
-            tx.send(buf)
-                .unwrap_or_else(|err| todo!("sending message to {} failed: {}", b_out, err));
+            let (buf, headers) = match rx.recv() {
+                Ok(buf) => buf,
+                Err(_) if stop.is_stopping() || rx.is_stopped() => return Ok(()),
+                Err(err) => return Err(err.into()),
+            };
+            // Replies and subscriptions are meaningful to routers only
+            if buf.is_control() {
+                continue;
+            }
+            topics.record(&buf);
+            match tx.send(buf, Some(&headers)) {
+                Ok(()) => {}
+                Err(_) if stop.is_stopping() || tx.is_stopped() => return Ok(()),
+                Err(err) => return Err(OrchestratorError::send(&b_out, None, err).into()),
+            }
         });
         self.pipes.push(handle);
         Ok(())
@@ -69,29 +567,47 @@ where
     ) -> anyhow::Result<()> {
         assert!(!out.is_empty());
         info!("setting communication {} -> {} topics", b_in, out.len());
-        let rx: Receiver = self.take_bridge_rx(b_in)?;
+        let mut rx = self.take_bridge_rx(b_in)?;
         let b_in = b_in.to_owned();
+        let stop = self.stop.clone();
+        let mut topics = self.stats.topics();
         let handle = tokio::task::spawn_blocking(move || loop {
-            let msg = rx
-                .recv()
-                .unwrap_or_else(|err| todo!("receiving message from {} failed: {}", b_in, err));
-            assert!(out.contains_key(&msg.topic));
-            let topic = msg.topic.clone();
-            out[&topic].send(msg).unwrap_or_else(|err| {
-                todo!(
-                    "sending message from {} to topic {} failed: {}",
-                    b_in,
-                    topic,
-                    err
-                )
-            });
+            let msg = match rx.recv() {
+                Ok((msg, _)) => msg,
+                Err(_) if stop.is_stopping() || rx.is_stopped() => return Ok(()),
+                Err(err) => return Err(err.into()),
+            };
+            if msg.is_control() {
+                continue;
+            }
+            topics.record(&msg);
+            let out = match out.get(&msg.topic) {
+                Some(out) => out,
+                None => {
+                    return Err(OrchestratorError::UnroutedTopic {
+                        bridge: Some(b_in),
+                        topic: msg.topic,
+                    }
+                    .into())
+                }
+            };
+            if let Err(err) = out.send(msg) {
+                if stop.is_stopping() {
+                    return Ok(());
+                }
+                return Err(OrchestratorError::RouterClosed {
+                    reason: format!("forwarding from `{}` failed: {}", b_in, err),
+                }
+                .into());
+            }
         });
         self.pipes.push(handle);
         Ok(())
     }
 
     /// Forward all messages from crossbeam Receiver to module b_out
-    /// Spawns pipe handler in a tokio blocking task thread
+    /// Spawns pipe handler in a tokio blocking task thread.
+    /// Should be called before router is started, as router takes senders of every bridge
     /// - b_out name of outgoing bridge from Self::bridges
     pub fn forward_bridge_tx(
         &mut self,
@@ -99,15 +615,26 @@ where
         input: channel::Receiver<Message>,
     ) -> anyhow::Result<()> {
         info!("setting communication topic -> {}", b_out);
-        let tx: Sender = self.take_bridge_tx(b_out)?;
+        let mut tx = self.take_bridge_tx(b_out)?;
         let b_out = b_out.to_owned();
+        let stop = self.stop.clone();
         let handle = tokio::task::spawn_blocking(move || loop {
-            let msg: Message = input
-                .recv()
-                .unwrap_or_else(|err| todo!("receiving message from {} failed: {}", b_out, err));
-            tx.send(msg).unwrap_or_else(|err| {
-                todo!("sending message from topic to {} failed: {}", b_out, err)
-            });
+            let msg: Message = crossbeam::select! {
+                recv(input) -> msg => match msg {
+                    Ok(msg) => msg,
+                    Err(err) => return Err(OrchestratorError::RouterClosed {
+                        reason: format!("forwarding to `{}` failed: {}", b_out, err),
+                    }
+                    .into()),
+                },
+                recv(stop.receiver()) -> _ => return Ok(()),
+            };
+            let topic = msg.topic.clone();
+            match tx.send(msg, None) {
+                Ok(()) => {}
+                Err(_) if stop.is_stopping() || tx.is_stopped() => return Ok(()),
+                Err(err) => return Err(OrchestratorError::send(&b_out, Some(&topic), err).into()),
+            }
         });
         self.pipes.push(handle);
         Ok(())
@@ -116,102 +643,371 @@ where
     /// Forward all messages received to topic to module bridge b_out
     /// This method only configures route, does not spawn handler.
     /// After route configuration done handler shall be started with `pipe_routes()`
-    /// - topic name of topic for incoming messages
-    /// - b_out name of outgoing bridge from Self::bridges
+    /// - topic name of topic for incoming messages or pattern like `metrics.*`, see `TopicPattern`
+    /// - b_out name of outgoing bridge from Self::bridges, might be routed from several topics
     pub fn route_topic_to_bridge(&mut self, topic: &str, b_out: &str) -> anyhow::Result<()> {
         info!("setting communication topic {} -> {}", topic, b_out);
-        let tx: Sender = self.take_bridge_tx(b_out)?;
-        match self.routes.as_mut() {
-            Some(r) => match r.get_mut(topic) {
-                Some(bridges) => bridges.push(tx),
-                None => {
-                    r.insert(topic.to_owned(), vec![tx]);
-                }
-            },
+        let topic = TopicPattern::new(topic)?;
+        let routed = match self.routes.as_ref() {
+            Some(routes) => routes.has_bridge(b_out),
             None => {
                 return Err(anyhow::anyhow!(
                     "cannot change routes after orchestrator started"
                 ))
             }
         };
+        if !routed {
+            let tx = self.take_bridge_tx(b_out)?;
+            self.routes.as_mut().unwrap().add_bridge(tx);
+        }
+        self.routes.as_mut().unwrap().route(topic, b_out)
+    }
+
+    /// Handle to change routes after router was started with one of `pipe_routes` methods,
+    /// see `RouteTable`
+    pub fn route_table(&self) -> anyhow::Result<RouteTable> {
+        Ok(RouteTable(self.updates()?.clone()))
+    }
+
+    /// Publish message to `topic`, router delivers it as message of a process.
+    /// Router should be started with one of `pipe_routes` methods
+    pub fn publish(&self, topic: &str, data: Vec<u8>) -> anyhow::Result<()> {
+        self.updates()?.publish(topic, data)
+    }
+
+    /// Send `message` to bridge `b_out` only, regardless of routes.
+    /// Router should be started with one of `pipe_routes` methods,
+    /// fails when `b_out` is neither connected process nor participant
+    pub fn send_to(&self, b_out: &str, message: Message) -> anyhow::Result<()> {
+        self.updates()?.send_to(b_out, message)
+    }
+
+    fn updates(&self) -> anyhow::Result<&Updates> {
+        self.router
+            .as_ref()
+            .map(|router| &router.updates)
+            .ok_or_else(|| anyhow!("router was not started"))
+    }
+
+    /// Record messages of `topics` or patterns delivered by router to capture file at `path`
+    /// until router stops, see `CaptureWriter`. Capture is appended if it exists
+    pub fn record(&mut self, path: impl AsRef<Path>, topics: &[&str]) -> anyhow::Result<()> {
+        let topics = topics
+            .iter()
+            .map(|topic| TopicPattern::new(topic))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let path = path.as_ref();
+        let writer = CaptureWriter::append(path)?;
+        let path = path.display().to_string();
+        info!("recording {} topics to {}", topics.len(), path);
+        let (tx, rx) = channel::unbounded();
+        let start = Instant::now();
+        self.add_tap(
+            topics,
+            Box::new(move |msg, headers| {
+                let record = Record {
+                    offset: start.elapsed(),
+                    source: headers.source.clone(),
+                    topic: msg.topic.clone(),
+                    data: msg.data.clone(),
+                };
+                tx.send(record).is_ok()
+            }),
+        )?;
+        let handle = tokio::task::spawn_blocking(move || {
+            capture::record(rx, writer, &path);
+            Ok(())
+        });
+        self.pipes.push(handle);
         Ok(())
     }
 
+    /// Stream of messages of `topic` or pattern delivered by router, routing is not affected.
+    /// Tap starts with router or right away if router is running, it is removed once stream is dropped.
+    ///
+    /// Messages are buffered for stream without limit, so it should be consumed
+    pub fn tap(&mut self, topic: &str) -> anyhow::Result<impl Stream<Item = Message>> {
+        let topic = TopicPattern::new(topic)?;
+        info!("tapping {}", topic);
+        let (tap, stream) = stream_tap();
+        self.add_tap(vec![topic], tap)?;
+        Ok(stream)
+    }
+
+    /// Replay capture file at `path` into running router in background at `pace`.
+    /// Messages are delivered by routes as if their source bridges sent them.
+    /// Returned task completes with number of replayed messages once capture ends
+    pub fn replay(
+        &self,
+        path: impl AsRef<Path>,
+        pace: Pace,
+    ) -> anyhow::Result<JoinHandle<anyhow::Result<usize>>> {
+        let updates = self.updates()?.clone();
+        let records = CaptureReader::open(path)?;
+        Ok(tokio::task::spawn_blocking(move || {
+            capture::replay(records, pace, |msg, headers| {
+                updates.send(Update::Inject(msg, headers))
+            })
+        }))
+    }
+
+    /// Observe messages delivered by router, tap is added to router once it is started
+    fn add_tap(&mut self, topics: Vec<TopicPattern>, tap: Tap) -> anyhow::Result<()> {
+        match (self.routes.as_mut(), self.router.as_ref()) {
+            (Some(routes), _) => {
+                routes.tap(topics, tap);
+                Ok(())
+            }
+            (None, Some(router)) => router.updates.send(Update::Tap(topics, tap)),
+            (None, None) => Err(OrchestratorError::RouterClosed {
+                reason: "router failed to start".to_owned(),
+            }
+            .into()),
+        }
+    }
+
+    /// Set what router does with messages to topics without recipients, see `DeadLetterPolicy`.
+    /// This method only configures routes, it should be called before router is started.
+    /// Bridge receiving dead letters might be routed from topics as well
+    pub fn dead_letters_policy(&mut self, policy: DeadLetterPolicy) -> anyhow::Result<()> {
+        info!("setting dead letters policy {:?}", policy);
+        let routes = self
+            .routes
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("cannot change routes after orchestrator started"))?;
+        if let DeadLetterPolicy::Bridge(name) = &policy {
+            if !routes.has_bridge(name) {
+                let tx = self.take_bridge_tx(name)?;
+                self.routes.as_mut().unwrap().add_bridge(tx);
+            }
+        }
+        self.routes.as_mut().unwrap().dead_letter(policy)
+    }
+
+    /// Messages received by router to topics without recipients
+    pub fn dead_letters(&self) -> DeadLetters {
+        self.stats.dead_letters().snapshot()
+    }
+
     /// Spawn router to process messages subscriptions built with `route_topic_to_bridge`
     /// Spawns handler in a tokio blocking task thread.
+    /// Router takes senders of bridges not used by pipes to reply to their requests
+    /// and to apply their subscriptions, so they cannot be forwarded to afterwards.
     ///
     /// # Might block
     /// This method is not using crossbeam as delivery buffer, hence should use less memory,
     /// though it might block if one of channels is not being processed
     pub fn pipe_routes(&mut self) -> anyhow::Result<()> {
+        let routes = self.take_routes()?;
+        self.spawn_router(routes, None)
+    }
+
+    /// Spawn router to process messages subscriptions built with `route_topic_to_bridge`
+    /// Spawns router in a tokio blocking task thread, which puts messages into bounded queue
+    /// of every recipient, and a blocking task thread per recipient sending its queue.
+    ///
+    /// Slow recipient neither blocks router nor makes it grow on memory,
+    /// when its queue of `capacity` messages is full `overflow` policy applies.
+    /// Queue depths and dropped messages are reported by `queues()`
+    pub fn pipe_routes_via_queues(
+        &mut self,
+        capacity: usize,
+        overflow: Overflow,
+    ) -> anyhow::Result<()> {
+        if capacity == 0 {
+            return Err(anyhow::anyhow!("queue capacity should be positive"));
+        }
+        let mut routes = self.take_routes()?;
+        for (queue, tx) in routes.queue(capacity, overflow) {
+            info!("setting up queue of {} to {}", capacity, queue.name());
+            self.spawn_queue(queue, tx);
+        }
+        self.spawn_router(routes, Some((capacity, overflow)))
+    }
+
+    /// Spawn thread sending queue to its bridge
+    fn spawn_queue(&mut self, queue: Arc<Queue>, tx: BridgeTx) {
+        self.stats.add_queue(queue.clone());
+        let stop = self.stop.clone();
+        let handle = tokio::task::spawn_blocking(move || queue.forward(tx, &stop));
+        self.pipes.push(handle);
+    }
+
+    /// State of recipient queues started with `pipe_routes_via_queues` by bridge name
+    pub fn queues(&self) -> HashMap<String, QueueStats> {
+        self.stats.queues()
+    }
+
+    /// Messages and bytes routed per topic, source and destination bridge,
+    /// with send errors, queue depths and dead letters
+    pub fn stats(&self) -> Stats {
+        self.stats.snapshot()
+    }
+
+    /// Serve Prometheus metrics at `addr`, e.g. "127.0.0.1:9898", until orchestrator stops.
+    /// Returns address listener is bound to, see `metrics` module
+    #[cfg(feature = "metrics")]
+    pub fn serve_metrics(&self, addr: &str) -> anyhow::Result<std::net::SocketAddr> {
+        let metrics = crate::metrics::Metrics {
+            states: self.live.clone(),
+            stats: self.stats.clone(),
+        };
+        crate::metrics::serve(addr, metrics, self.stop.clone())
+    }
+
+    /// Log statistics every `interval` from background thread until orchestrator stops
+    pub fn log_stats(&self, interval: Duration) {
+        let stats = self.stats.clone();
+        let stop = self.stop.clone();
+        std::thread::spawn(move || loop {
+            crossbeam::select! {
+                recv(stop.receiver()) -> _ => return,
+                default(interval) => info!("stats {}", stats.snapshot()),
+            }
+        });
+    }
+
+    /// Spawn thread receiving messages from every bridge and delivering them by `routes`,
+    /// messages are put into recipient `queues` if they are set up
+    fn spawn_router(
+        &mut self,
+        mut routes: Routes,
+        queues: Option<(usize, Overflow)>,
+    ) -> anyhow::Result<()> {
         info!("starting communication thread");
-        let mut ipc_receiver_set = IpcReceiverSet::new().unwrap();
-        let mut names: HashMap<u64, String> = HashMap::new();
+        let mut receivers = Receivers {
+            set: IpcReceiverSet::new().unwrap(),
+            names: HashMap::new(),
+            reconnects: HashMap::new(),
+            left: HashSet::new(),
+            states: HashMap::new(),
+            sources: HashMap::new(),
+            stamps: HashMap::new(),
+        };
         let bridge_names: Vec<String> = self.bridges.keys().cloned().collect();
         for name in bridge_names {
             if let Ok(recv) = self.take_bridge_rx(&name) {
-                info!("setting up receiver {}", name);
-                let id = ipc_receiver_set.add(recv)?;
-                names.insert(id, name.to_string());
+                receivers.add(recv)?;
             }
         }
-        let routes = self
-            .routes
-            .take()
-            .ok_or_else(|| anyhow::anyhow!("routes were not configured"))?;
-
+        // Bridges join and leave running router via membership channel,
+        // doorbell wakes router up as it waits on IPC channels only
+        let (updates, changes) = channel::unbounded();
+        let (doorbell, bell) = ipc::channel()?;
+        let bell = receivers.set.add(bell)?;
+        self.router = Some(RouterLink {
+            updates: Updates {
+                tx: updates,
+                doorbell: Some(doorbell),
+                live: self.live.clone(),
+            },
+            feed: None,
+            queues,
+        });
+        let stop = self.stop.clone();
         let handle = tokio::task::spawn_blocking(move || loop {
-            let results = match ipc_receiver_set.select() {
+            let results = match receivers.set.select() {
                 Ok(results) => results,
-                Err(err) => todo!("receiving message failed: {}", err),
+                Err(err) => {
+                    return Err(OrchestratorError::ReceiveFailed {
+                        bridge: None,
+                        reason: err.to_string(),
+                    }
+                    .into())
+                }
             };
             for event in results {
                 match event {
+                    // Every update rings once, so that updates are interleaved with messages
+                    // and replay is held back by doorbell channel
+                    IpcSelectionResult::MessageReceived(id, _) if id == bell => {
+                        if let Ok(change) = changes.try_recv() {
+                            if let Update::Leave(name, _) = &change {
+                                receivers.leave(name);
+                            }
+                            if let Some(recv) = change.apply(&mut routes, &stop)? {
+                                receivers.add(recv)?;
+                            }
+                        }
+                    }
+                    IpcSelectionResult::MessageReceived(id, message)
+                        if receivers.reconnects.contains_key(&id) =>
+                    {
+                        let name = receivers.reconnects[&id].clone();
+                        let recv: Receiver =
+                            message
+                                .to()
+                                .map_err(|err| OrchestratorError::HandshakeFailed {
+                                    bridge: name.clone(),
+                                    reason: format!(
+                                        "receiving channel of restarted process: {}",
+                                        err
+                                    ),
+                                })?;
+                        info!("receiving from restarted {}", name);
+                        let id = receivers.set.add(recv).map_err(|err| {
+                            OrchestratorError::HandshakeFailed {
+                                bridge: name.clone(),
+                                reason: format!("receiving from restarted process: {}", err),
+                            }
+                        })?;
+                        receivers.names.insert(id, name);
+                    }
                     IpcSelectionResult::MessageReceived(id, message) => {
-                        let msg: Message = message.to().unwrap_or_else(|err| {
-                            todo!(
-                                "receiving message from {:?} failed: {}",
-                                names.get(&id),
-                                err
-                            )
-                        });
-                        let senders = routes.get(&msg.topic).unwrap_or_else(|| {
-                            todo!(
-                                "received message from {:?} to topic {} without recepients",
-                                names.get(&id),
-                                msg.topic
-                            )
-                        });
-                        let except_last = senders.len() - 1;
-                        trace!(
-                            "sending message from topic {} to {} senders",
-                            msg.topic,
-                            senders.len()
-                        );
-                        for (i, tx) in senders[0..except_last].iter().enumerate() {
-                            tx.send(msg.clone()).unwrap_or_else(|err| {
-                                todo!(
-                                    "sending message from topic {} to {} failed: {}",
-                                    msg.topic,
-                                    i,
-                                    err
-                                )
-                            });
+                        let msg: Message =
+                            message
+                                .to()
+                                .map_err(|err| OrchestratorError::ReceiveFailed {
+                                    bridge: receivers.names.get(&id).cloned(),
+                                    reason: err.to_string(),
+                                })?;
+                        let (msg, sent) = msg.split_headers();
+                        let Receivers {
+                            names,
+                            states,
+                            sources,
+                            stamps,
+                            ..
+                        } = &mut receivers;
+                        let name = names.get(&id);
+                        if let Some(state) = name.and_then(|n| states.get(n)) {
+                            state.seen(&msg);
                         }
-                        let topic = msg.topic.clone();
-                        senders.last().unwrap().send(msg).unwrap_or_else(|err| {
-                            todo!(
-                                "sending message from topic {} to last sender failed: {}",
-                                topic,
-                                err
-                            )
-                        });
+                        if msg.is_control() && !msg.is_for_router() {
+                            trace!("control message from {:?}", name);
+                            continue;
+                        }
+                        if let Some(counter) = name.and_then(|n| sources.get(n)) {
+                            counter.record(msg.data.len());
+                        }
+                        let headers = name
+                            .and_then(|n| stamps.get_mut(n))
+                            .map(|stamp| stamp.stamp(sent))
+                            .unwrap_or_default();
+                        routes.deliver(msg, &headers, &stop)?;
+                    }
+                    IpcSelectionResult::ChannelClosed(id) if id == bell => {}
+                    IpcSelectionResult::ChannelClosed(id)
+                        if receivers.reconnects.contains_key(&id) =>
+                    {
+                        receivers.reconnects.remove(&id);
+                        receivers.left.remove(&id);
                     }
                     IpcSelectionResult::ChannelClosed(id) => {
-                        error!("Channel from {:?} closed...", names.get(&id));
+                        let name = receivers.names.remove(&id);
+                        if receivers.left.remove(&id) {
+                            info!("channel from stopped {:?} closed", name);
+                        } else if !stop.is_stopping() {
+                            error!("Channel from {:?} closed...", name);
+                        }
                     }
                 }
             }
+            // Set with no receivers would block forever
+            if receivers.names.is_empty() && receivers.reconnects.is_empty() {
+                info!("all the channels closed, stopping router");
+                return Ok(());
+            }
         });
         self.pipes.push(handle);
         Ok(())
@@ -231,30 +1027,28 @@ where
     /// # Might grow on memory
     /// This method is using crossbeam as delivery buffer, hence it won't block,
     /// though it might use excessive memory to store not processed messages.
+    /// See `pipe_routes_via_queues` for bounded alternative.
     pub fn pipe_routes_via_crossbeam(&mut self) -> anyhow::Result<()> {
         info!("starting communication thread");
-        let routes = self
-            .routes
-            .take()
-            .ok_or_else(|| anyhow::anyhow!("routes were not configured"))?;
+        let mut routes = self.take_routes()?;
         let (tx, rx) = crossbeam::channel::unbounded();
 
         let bridge_names: Vec<String> = self.bridges.keys().cloned().collect();
         for name in bridge_names {
-            let tx = tx.clone();
             if let Ok(ipc) = self.take_bridge_rx(&name) {
-                info!("setting up receiver {}", name);
-                let handle = tokio::task::spawn_blocking(move || loop {
-                    let msg: Message = ipc.recv().unwrap_or_else(|err| {
-                        todo!("receiving message from {} failed: {}", name, err)
-                    });
-                    tx.send(msg).unwrap_or_else(|err| {
-                        todo!("sending message from {} failed: {}", name, err)
-                    });
-                });
-                self.pipes.push(handle);
+                self.spawn_receiver(ipc, tx.clone());
             }
         }
+        let (updates, mut changes) = channel::unbounded();
+        self.router = Some(RouterLink {
+            updates: Updates {
+                tx: updates,
+                doorbell: None,
+                live: self.live.clone(),
+            },
+            feed: Some(tx),
+            queues: None,
+        });
 
         // Spawn thread receiving messages from processes to topics
         /*let handle1 = tokio::task::spawn_blocking(move || loop {
@@ -291,89 +1085,578 @@ where
         self.pipes.push(handle1);*/
 
         // Spawn thread sending messages from topics to processes
+        let stop = self.stop.clone();
         let handle2 = tokio::task::spawn_blocking(move || loop {
-            let msg = rx.recv()?;
-            let senders = routes.get(&msg.topic).unwrap_or_else(|| {
-                todo!("received message to topic {} without recepients", msg.topic)
-            });
-            let except_last = senders.len() - 1;
-            trace!(
-                "sending message from topic {} to {} senders",
-                msg.topic,
-                senders.len()
-            );
-            for (i, tx) in senders[0..except_last].iter().enumerate() {
-                tx.send(msg.clone()).unwrap_or_else(|err| {
-                    todo!(
-                        "sending message from topic {} to {} failed: {}",
-                        msg.topic,
-                        i,
-                        err
-                    )
-                });
-            }
-            let topic = msg.topic.clone(); // TODO - see if it impacting perf
-            senders.last().unwrap().send(msg).unwrap_or_else(|err| {
-                todo!(
-                    "sending message from topic {} to last sender failed: {}",
-                    topic,
-                    err
-                )
-            });
+            let msg = crossbeam::select! {
+                recv(rx) -> msg => msg,
+                recv(changes) -> change => {
+                    match change {
+                        Ok(change) => {
+                            change.apply(&mut routes, &stop)?;
+                        }
+                        // Orchestrator is gone, no more bridges join
+                        Err(_) => changes = channel::never(),
+                    }
+                    continue;
+                }
+            };
+            let msg = match msg {
+                Ok(msg) => msg,
+                Err(_) if stop.is_stopping() => return Ok(()),
+                Err(err) => {
+                    return Err(OrchestratorError::RouterClosed {
+                        reason: err.to_string(),
+                    }
+                    .into())
+                }
+            };
+            let (msg, headers) = msg;
+            routes.deliver(msg, &headers, &stop)?;
         });
         self.pipes.push(handle2);
         Ok(())
     }
 
+    /// Spawn thread receiving messages from bridge to crossbeam router
+    fn spawn_receiver(&mut self, mut ipc: BridgeRx, tx: channel::Sender<(Message, Headers)>) {
+        info!("setting up receiver {}", ipc.name);
+        let stop = self.stop.clone();
+        let handle = tokio::task::spawn_blocking(move || loop {
+            let msg = match ipc.recv() {
+                Ok(msg) => msg,
+                Err(_) if stop.is_stopping() || ipc.is_stopped() => return Ok(()),
+                Err(err) => return Err(err.into()),
+            };
+            if let Err(err) = tx.send(msg) {
+                return Err(OrchestratorError::RouterClosed {
+                    reason: format!("forwarding from `{}` failed: {}", ipc.name, err),
+                }
+                .into());
+            }
+        });
+        self.pipes.push(handle);
+    }
+
     /// Run processes to completion
-    pub async fn run(self) -> anyhow::Result<()> {
-        let Self {
-            pipes,
-            mut loggers,
-            mut processes,
-            ..
-        } = self;
-        let skip_pipes = pipes.is_empty();
-        let pipes = try_join_all(pipes).fuse();
-        pin_mut!(pipes);
+    pub async fn run(mut self) -> anyhow::Result<()> {
+        self.watch().await
+    }
+
+    /// Run processes until `shutdown` future completes, then shutdown gracefully,
+    /// giving processes `grace` period to exit, see `shutdown()`
+    ///
+    /// Shutdown trigger might be any future, e.g. `tokio::signal::ctrl_c()`
+    /// or receiver of a oneshot channel.
+    pub async fn run_until<F>(mut self, shutdown: F, grace: Duration) -> anyhow::Result<ExitReport>
+    where
+        F: Future<Output = ()>,
+    {
+        {
+            let watch = self.watch().fuse();
+            let shutdown = shutdown.fuse();
+            pin_mut!(watch, shutdown);
+            select!(
+                res = watch => res?,
+                _ = shutdown => info!("shutdown requested"),
+            );
+        }
+        self.shutdown(grace).await
+    }
+
+    /// Gracefully stop all the processes and routing threads:
+    /// 1. send SIGTERM to every process, processes are no more restarted;
+    ///    processes which others depend on receive it once their dependents exit
+    /// 2. wait for processes to exit within `grace` period
+    /// 3. send SIGKILL to the processes which are still running
+    /// 4. stop routing threads
+    ///
+    /// Returns report with processes exit statuses
+    pub async fn shutdown(mut self, grace: Duration) -> anyhow::Result<ExitReport> {
+        info!("shutting down processes");
+        let mut report = ExitReport::default();
+        // Signal supervisors to not restart processes
+        drop(self.stop_tx.take());
+
+        // Stop processes in reverse dependency order
+        let deadline = Instant::now() + grace;
+        let mut running: Vec<String> = self.states.keys().cloned().collect();
+        let mut exited = true;
+        while exited && !running.is_empty() {
+            let (wave, rest): (Vec<String>, Vec<String>) =
+                running.iter().cloned().partition(|name| {
+                    !running.iter().any(|other| {
+                        self.dependencies
+                            .get(other)
+                            .is_some_and(|deps| deps.contains(name))
+                    })
+                });
+            for name in wave.iter() {
+                signal(self.states[name].pid.load(Ordering::Relaxed), libc::SIGTERM);
+            }
+            exited = self
+                .wait_exited(&wave, deadline.saturating_duration_since(Instant::now()))
+                .await;
+            running = rest;
+        }
+
+        if !exited {
+            for (name, state) in self.states.iter() {
+                if signal(state.pid.load(Ordering::Relaxed), libc::SIGKILL) {
+                    warn!("process `{}` did not exit within {:?}, killed", name, grace);
+                    report.killed.push(name.clone());
+                }
+            }
+            if !self.wait_processes(grace).await {
+                error!("processes did not exit after being killed");
+            }
+        }
+
+        // Participants return once they leave router and their channels close
+        let participants: Vec<String> = self.participants.keys().cloned().collect();
+        for name in participants {
+            if let Err(err) = self.stop(&name, grace).await {
+                warn!("participant `{}` did not leave router: {}", name, err);
+            }
+        }
 
+        // All the processes exit, hence routing threads will get their channels closed
+        self.router = None;
+        let pipes = &mut self.pipes;
+        let errors = &mut report.errors;
+        let stopped = tokio::time::timeout(grace, async {
+            while let Some(res) = pipes.next().await {
+                match res {
+                    Ok(Ok(())) => {}
+                    Ok(Err(err)) => errors.push(err.to_string()),
+                    Err(err) => errors.push(err.to_string()),
+                }
+            }
+        })
+        .await;
+        if stopped.is_err() {
+            report
+                .errors
+                .push(format!("routing threads did not stop within {:?}", grace));
+        }
+
+        for (name, state) in self.states.iter() {
+            report
+                .statuses
+                .insert(name.clone(), *state.exit.lock().unwrap());
+        }
+        info!("shutdown complete");
+        Ok(report)
+    }
+
+    /// Wait for supervised processes to complete, false if timeout elapsed
+    async fn wait_processes(&mut self, timeout: Duration) -> bool {
+        let processes = &mut self.processes;
+        tokio::time::timeout(timeout, async {
+            while let Some(res) = processes.next().await {
+                if let Err(err) = res {
+                    error!("processes failure: {}", err);
+                }
+            }
+        })
+        .await
+        .is_ok()
+    }
+
+    /// Wait for processes `names` to exit, false if timeout elapsed.
+    /// Failure of another process meanwhile is kept to be reported by `run`
+    async fn wait_exited(&mut self, names: &[String], timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
         loop {
-            if skip_pipes {
-                select!(
-                    res = loggers => should_not_complete!("logs", res),
-                    res = processes => should_not_complete!("processes", res),
-                )
-            } else {
+            let exited = names.iter().all(|name| !self.states[name].is_running());
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if exited || self.processes.is_empty() {
+                return true;
+            }
+            if remaining == Duration::from_secs(0) {
+                return false;
+            }
+            let step = remaining.min(EXIT_POLL_INTERVAL);
+            if let Ok(Some(Err(err))) = tokio::time::timeout(step, self.processes.next()).await {
+                error!("processes failure: {}", err);
+                self.failed.get_or_insert(err);
+            }
+        }
+    }
+
+    /// Watch processes and routing threads, completes with error when any of them fail.
+    /// Serves requests of `OrchestratorHandle` meanwhile
+    async fn watch(&mut self) -> anyhow::Result<()> {
+        let skip_pipes = self.pipes.is_empty();
+
+        loop {
+            if let Some(err) = self.failed.take() {
+                return Err(err);
+            }
+            let event = {
+                let mut pipes = join_pipes(&mut self.pipes).fuse();
+                if skip_pipes {
+                    pipes = Fuse::terminated();
+                }
+                pin_mut!(pipes);
                 select!(
-                    res = pipes => should_not_complete!("channels", res) as anyhow::Result<()>,
-                    res = loggers => never_fail!("logs", res),
-                    res = processes => may_complete!("processes", res),
+                    res = pipes => Event::Channels(res),
+                    res = self.processes.select_next_some() => Event::Process(res),
+                    (name, ready, done) = self.joining.select_next_some() => {
+                        Event::Joined(name, ready, done)
+                    },
+                    control = self.control.1.select_next_some() => Event::Control(control),
                 )
-            }?
+            };
+            match event {
+                Event::Channels(res) => return should_not_complete!("channels", res),
+                Event::Process(Err(err)) => {
+                    error!("processes failure: {}", err);
+                    return Err(err);
+                }
+                Event::Process(Ok(())) if self.processes.is_empty() => {
+                    info!("All the processes completed");
+                    let stopped = self
+                        .states
+                        .values()
+                        .all(|state| state.stopped.load(Ordering::Relaxed));
+                    if skip_pipes && !stopped {
+                        return Err(anyhow!("All the processes exit"));
+                    }
+                }
+                Event::Process(Ok(())) => {}
+                Event::Joined(name, ready, done) => {
+                    let _ = done.send(self.join(&name, ready));
+                }
+                Event::Control(control) => self.serve(control).await,
+            }
+        }
+    }
+
+    /// Serve request of `OrchestratorHandle`, spawned process joins once it gets ready
+    async fn serve(&mut self, control: Control) {
+        match control {
+            Control::Spawn {
+                name,
+                cmd,
+                policy,
+                done,
+            } => match self.start(&name, *cmd, policy) {
+                Ok(ready) => self
+                    .joining
+                    .push(Box::pin(ready.map(move |ready| (name, ready, done)))),
+                Err(err) => {
+                    let _ = done.send(Err(err));
+                }
+            },
+            Control::Stop { name, grace, done } => {
+                let _ = done.send(self.stop(&name, grace).await);
+            }
+        }
+    }
+
+    /// Start process under supervision, returns its readiness
+    fn start(
+        &mut self,
+        name: &str,
+        cmd: Command,
+        policy: RestartPolicy,
+    ) -> anyhow::Result<BFR<Ready>> {
+        if let Some(state) = self.states.get(name) {
+            if !state.stopped.load(Ordering::Relaxed) || state.is_running() {
+                return Err(anyhow!("process named `{}` already started", name));
+            }
+        }
+        if self.participants.contains_key(name) {
+            return Err(anyhow!("in-process participant named `{}` exists", name));
+        }
+        info!("spawning {}", name);
+        let Spawned {
+            state,
+            process,
+            ready,
+        } = self.spawner.spawn(name, cmd, policy)?;
+        let live = state.clone();
+        self.live.write().unwrap().insert(name.to_owned(), live);
+        self.states.insert(name.to_owned(), state);
+        self.processes.push(process);
+        Ok(ready)
+    }
+
+    /// Connect bridge of spawned process to running router, process which is not ready is killed
+    fn join(&mut self, name: &str, ready: anyhow::Result<Ready>) -> anyhow::Result<()> {
+        let bridge = match ready {
+            Ok(Ready {
+                bridge: Some(bridge),
+                ..
+            }) => bridge,
+            Ok(Ready { bridge: None, .. }) => return Ok(()),
+            Err(err) => {
+                error!("{} did not get ready, killing: {}", name, err);
+                let state = &self.states[name];
+                state.stopped.store(true, Ordering::Relaxed);
+                signal(state.pid.load(Ordering::Relaxed), libc::SIGKILL);
+                return Err(err);
+            }
+        };
+        info!("{} is ready", name);
+        self.bridges.insert(name.to_owned(), bridge);
+        self.join_router(name)
+    }
+
+    /// Connect bridge `name` to router if it is running
+    fn join_router(&mut self, name: &str) -> anyhow::Result<()> {
+        let (queues, feed) = match self.router.as_ref() {
+            Some(router) => (router.queues, router.feed.clone()),
+            None => return Ok(()),
+        };
+        let tx = self.take_bridge_tx(name)?;
+        let output = match queues {
+            Some((capacity, overflow)) => {
+                let (queue, queue_tx) = Queue::new(name, capacity, overflow);
+                self.spawn_queue(queue.clone(), tx);
+                Output::Queue(queue, queue_tx)
+            }
+            None => Output::Bridge(tx),
+        };
+        let rx = self.take_bridge_rx(name)?;
+        let rx = match feed {
+            Some(feed) => {
+                self.spawn_receiver(rx, feed);
+                None
+            }
+            None => Some(rx),
+        };
+        let router = self.router.as_ref().unwrap();
+        router.updates.send(Update::Join(rx, output))?;
+        let subscriptions = self.state(name).map(|state| state.take_subscriptions());
+        for msg in subscriptions.unwrap_or_default() {
+            router
+                .updates
+                .send(Update::Inject(msg, process_headers(name)))?;
+        }
+        Ok(())
+    }
+}
+
+impl RouteTable {
+    /// Route messages of `topic` or pattern to bridge `b_out` from now on
+    pub fn route(&self, topic: &str, b_out: &str) -> impl Future<Output = anyhow::Result<()>> {
+        self.apply(RouteChanges::new().route(topic, b_out))
+    }
+
+    /// Stop routing messages of `topic` or pattern to bridge `b_out`
+    pub fn unroute(&self, topic: &str, b_out: &str) -> impl Future<Output = anyhow::Result<()>> {
+        self.apply(RouteChanges::new().unroute(topic, b_out))
+    }
+
+    /// Publish message to `topic`, see `ConnectedOrchestrator::publish`
+    pub fn publish(&self, topic: &str, data: Vec<u8>) -> anyhow::Result<()> {
+        self.0.publish(topic, data)
+    }
+
+    /// Send `message` to bridge `b_out` only, see `ConnectedOrchestrator::send_to`
+    pub fn send_to(&self, b_out: &str, message: Message) -> anyhow::Result<()> {
+        self.0.send_to(b_out, message)
+    }
+
+    /// Stream of messages of `topic` or pattern delivered by router, see `ConnectedOrchestrator::tap`
+    pub fn tap(&self, topic: &str) -> anyhow::Result<impl Stream<Item = Message>> {
+        let topic = TopicPattern::new(topic)?;
+        info!("tapping {}", topic);
+        let (tap, stream) = stream_tap();
+        self.0.send(Update::Tap(vec![topic], tap))?;
+        Ok(stream)
+    }
+
+    /// Apply all the `changes` at once: every message is delivered either by routes before
+    /// or by routes after changes, neither lost nor duplicated.
+    /// Changes are rejected altogether if any of them is invalid.
+    ///
+    /// Changes are sent to router right away, returned future completes once they are applied
+    pub fn apply(&self, changes: RouteChanges) -> impl Future<Output = anyhow::Result<()>> {
+        let (done, applied) = oneshot::channel();
+        let sent = self.0.send(Update::Routes(changes, done));
+        async move {
+            sent?;
+            applied.await.map_err(|_| OrchestratorError::RouterClosed {
+                reason: "router stopped".to_owned(),
+            })?
         }
     }
 }
 
+impl OrchestratorHandle {
+    /// Start process `name`, see `ConnectedOrchestrator::spawn`
+    pub async fn spawn(&self, name: &str, cmd: Command) -> anyhow::Result<()> {
+        self.spawn_with_policy(name, cmd, RestartPolicy::never())
+            .await
+    }
+
+    /// Start process `name` with restart policy, see `ConnectedOrchestrator::spawn_with_policy`
+    pub async fn spawn_with_policy(
+        &self,
+        name: &str,
+        cmd: Command,
+        policy: RestartPolicy,
+    ) -> anyhow::Result<()> {
+        self.request(|done| Control::Spawn {
+            name: name.to_owned(),
+            cmd: Box::new(cmd),
+            policy,
+            done,
+        })
+        .await
+    }
+
+    /// Stop process `name`, see `ConnectedOrchestrator::stop`
+    pub async fn stop(&self, name: &str, grace: Duration) -> anyhow::Result<Option<ExitStatus>> {
+        self.request(|done| Control::Stop {
+            name: name.to_owned(),
+            grace,
+            done,
+        })
+        .await
+    }
+
+    async fn request<T>(&self, control: impl FnOnce(Reply<T>) -> Control) -> anyhow::Result<T> {
+        let (done, result) = oneshot::channel();
+        self.0
+            .unbounded_send(control(done))
+            .map_err(|_| anyhow!("orchestrator stopped"))?;
+        result.await.map_err(|_| anyhow!("orchestrator stopped"))?
+    }
+}
+
+/// Tap sending messages to stream, it is removed once stream is dropped
+fn stream_tap() -> (Tap, mpsc::UnboundedReceiver<Message>) {
+    let (tx, rx) = mpsc::unbounded();
+    let tap = Box::new(move |msg: &Message, _: &Headers| tx.unbounded_send(msg.clone()).is_ok());
+    (tap, rx)
+}
+
+/// Wait for all the routing threads to complete or any of them to fail
+async fn join_pipes(
+    pipes: &mut FuturesUnordered<JoinHandle<anyhow::Result<()>>>,
+) -> anyhow::Result<()> {
+    while let Some(res) = pipes.next().await {
+        res??;
+    }
+    Ok(())
+}
+
 // Some utilities
-impl<LF> ConnectedOrchestrator<LF>
-where
-    LF: FusedFuture<Output = anyhow::Result<Vec<()>>>,
-{
-    fn take_bridge_rx(&mut self, name: &str) -> anyhow::Result<Receiver> {
-        self.bridges
+impl ConnectedOrchestrator {
+    /// Routes to start router with, senders of bridges not used by pipes are added
+    /// so that router can reply to their requests
+    fn take_routes(&mut self) -> anyhow::Result<Routes> {
+        let mut routes = self
+            .routes
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("routes were not configured"))?;
+        let bridge_names: Vec<String> = self.bridges.keys().cloned().collect();
+        for name in bridge_names {
+            if !routes.has_bridge(&name) {
+                if let Ok(tx) = self.take_bridge_tx(&name) {
+                    routes.add_bridge(tx);
+                }
+            }
+        }
+        for (name, state) in self.states.iter() {
+            for msg in state.take_subscriptions() {
+                routes.subscription(&msg, &process_headers(name));
+            }
+        }
+        Ok(routes)
+    }
+
+    /// State of process or in-process participant `name`
+    fn state(&self, name: &str) -> Option<Arc<ProcessState>> {
+        self.participants
+            .get(name)
+            .or_else(|| self.states.get(name))
+            .cloned()
+    }
+
+    fn take_bridge_rx(&mut self, name: &str) -> anyhow::Result<BridgeRx> {
+        let state = self.state(name);
+        let bridge = self
+            .bridges
             .get_mut(name)
-            .ok_or_else(|| anyhow!("destination module `{}` bridge not found", name))?
+            .ok_or_else(|| anyhow!("destination module `{}` bridge not found", name))?;
+        let rx = bridge
             .channel
             .rx_take()
-            .ok_or_else(|| anyhow!("Failed to get receiver from {}", name))
+            .ok_or_else(|| anyhow!("Failed to get receiver from {}", name))?;
+        Ok(BridgeRx::new(
+            name.to_owned(),
+            rx,
+            bridge.rx_reconnects.take(),
+            state,
+            self.stats.source(name),
+        ))
     }
 
-    fn take_bridge_tx(&mut self, name: &str) -> anyhow::Result<Sender> {
-        self.bridges
+    fn take_bridge_tx(&mut self, name: &str) -> anyhow::Result<BridgeTx> {
+        let state = self.state(name);
+        let bridge = self
+            .bridges
             .get_mut(name)
-            .ok_or_else(|| anyhow!("source module `{}` bridge not found", name))?
-            .channel
-            .tx_take()
-            .ok_or_else(|| anyhow!("Failed to get sender from `{}`", name))
+            .ok_or_else(|| anyhow!("source module `{}` bridge not found", name))?;
+        let router = self.router.is_some();
+        let tx = bridge.channel.tx_take().ok_or_else(|| match router {
+            true => anyhow!(
+                "sender of `{}` is taken by pipe or by router, which takes senders of every \
+                 bridge once started, forward to `{}` before starting router",
+                name,
+                name
+            ),
+            false => anyhow!("Failed to get sender from `{}`", name),
+        })?;
+        Ok(BridgeTx::new(
+            name.to_owned(),
+            tx,
+            bridge.tx_reconnects.take(),
+            state,
+            self.stats.destination(name),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn send_to_unknown_bridge_fails() {
+        let (tx, changes) = channel::unbounded();
+        let live = LiveStates::default();
+        let updates = Updates {
+            tx,
+            doorbell: None,
+            live: live.clone(),
+        };
+        let state = Arc::new(ProcessState::default());
+        live.write()
+            .unwrap()
+            .insert("write".to_owned(), state.clone());
+        let message = || Message {
+            topic: "start".to_owned(),
+            data: vec![],
+        };
+
+        let err = updates.send_to("missing", message()).unwrap_err();
+        assert_eq!(err.to_string(), "bridge `missing` not found");
+        // Process which is not connected via IPC has no bridge
+        assert!(updates.send_to("write", message()).is_err());
+        assert!(changes.try_recv().is_err());
+
+        state.connected.store(true, Ordering::Relaxed);
+        updates.send_to("write", message()).unwrap();
+        match changes.try_recv() {
+            Ok(Update::SendTo(name, msg, _)) => {
+                assert_eq!((name.as_str(), msg.topic.as_str()), ("write", "start"))
+            }
+            _ => panic!("message was not sent to router"),
+        }
     }
 }
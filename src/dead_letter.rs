@@ -0,0 +1,179 @@
+//! Dead letters: messages to topics without recipients
+//!
+//! By default router drops such message and `Log`s warning, at most one per second,
+//! so that a single stray message does not take down the whole pipeline.
+//! With `ConnectedOrchestrator::dead_letters_policy` router might instead
+//! - `Fail` with `OrchestratorError::UnroutedTopic`, e.g. for tests and strict pipelines
+//! - `Drop` message silently
+//! - forward message to `Bridge`, e.g. logger process
+//! - forward message to recipients of `Topic`
+//!
+//! Message keeps its original topic when forwarded. Every dead letter is counted
+//! and the recent ones are kept to be inspected with `ConnectedOrchestrator::dead_letters()`,
+//! so that misspelled topic shows up clearly.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use tokio::process::Command;
+//! use ipc_orchestrator::{orchestrator, DeadLetterPolicy};
+//!
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//!     let mut orchestrator = orchestrator().ipc(false);
+//!     orchestrator.start("echo", &mut Command::new("echo")).unwrap();
+//!     let mut orchestra = orchestrator.connect().await.unwrap();
+//!     orchestra.dead_letters_policy(DeadLetterPolicy::Fail).unwrap();
+//!     assert_eq!(orchestra.dead_letters().count, 0);
+//!     # orchestra.shutdown(Duration::from_secs(1)).await.unwrap();
+//! # });
+//! ```
+
+use crate::message::Message;
+use log::warn;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Number of recent dead letters kept for inspection
+const RECENT_DEAD_LETTERS: usize = 100;
+/// Dead letters are logged at most once per this interval
+const LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What router does with messages to topics without recipients
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeadLetterPolicy {
+    /// Fail router with `OrchestratorError::UnroutedTopic`
+    Fail,
+    /// Drop message
+    Drop,
+    /// Drop message logging warning, rate limited
+    #[default]
+    Log,
+    /// Forward message to bridge
+    Bridge(String),
+    /// Forward message to recipients of topic
+    Topic(String),
+}
+
+/// Message received to topic without recipients
+#[derive(Clone, Debug)]
+pub struct DeadLetter {
+    /// Bridge message was received from, None when it is not known
+    pub bridge: Option<String>,
+    pub topic: String,
+    /// Size of message data in bytes
+    pub size: usize,
+    pub time: SystemTime,
+}
+
+/// Dead letters received by routers
+#[derive(Clone, Debug, Default)]
+pub struct DeadLetters {
+    /// Total number of dead letters
+    pub count: u64,
+    /// Number of dead letters per topic
+    pub topics: HashMap<String, u64>,
+    /// Most recent dead letters, oldest first
+    pub recent: VecDeque<DeadLetter>,
+}
+
+/// Dead letters shared by routers and orchestrator
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DeadLetterLog(Arc<Mutex<Log>>);
+
+#[derive(Debug, Default)]
+struct Log {
+    letters: DeadLetters,
+    logged: Option<Instant>,
+    suppressed: u64,
+}
+
+impl DeadLetterLog {
+    /// Count dead letter `msg` from `bridge`, logging it if `log` is set and rate limit allows
+    pub fn record(&self, msg: &Message, bridge: Option<&str>, log: bool) {
+        let mut state = self.0.lock().unwrap();
+        let letters = &mut state.letters;
+        letters.count += 1;
+        *letters.topics.entry(msg.topic.clone()).or_default() += 1;
+        if letters.recent.len() == RECENT_DEAD_LETTERS {
+            letters.recent.pop_front();
+        }
+        letters.recent.push_back(DeadLetter {
+            bridge: bridge.map(str::to_owned),
+            topic: msg.topic.clone(),
+            size: msg.data.len(),
+            time: SystemTime::now(),
+        });
+        if !log {
+            return;
+        }
+        if state.logged.is_some_and(|at| at.elapsed() < LOG_INTERVAL) {
+            state.suppressed += 1;
+            return;
+        }
+        warn!(
+            "dropped message from `{}` to topic `{}` without recipients, {} more dropped since last warning",
+            bridge.unwrap_or("unknown"),
+            msg.topic,
+            state.suppressed
+        );
+        state.logged = Some(Instant::now());
+        state.suppressed = 0;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.0.lock().unwrap().letters.count
+    }
+
+    pub fn snapshot(&self) -> DeadLetters {
+        self.0.lock().unwrap().letters.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(topic: &str) -> Message {
+        Message {
+            topic: topic.to_owned(),
+            data: vec![0; 4],
+        }
+    }
+
+    #[test]
+    fn warnings_are_rate_limited() {
+        let log = DeadLetterLog::default();
+        for _ in 0..3 {
+            log.record(&message("misspelled"), Some("source"), true);
+        }
+        assert_eq!(log.0.lock().unwrap().suppressed, 2);
+
+        // Next warning reports suppressed dead letters once interval elapsed
+        let logged = Instant::now().checked_sub(LOG_INTERVAL).unwrap();
+        log.0.lock().unwrap().logged = Some(logged);
+        log.record(&message("misspelled"), Some("source"), true);
+        let state = log.0.lock().unwrap();
+        assert_eq!(state.suppressed, 0);
+        assert!(state.logged.unwrap() > logged);
+        assert_eq!(state.letters.count, 4);
+        assert_eq!(state.letters.topics["misspelled"], 4);
+    }
+
+    #[test]
+    fn dead_letters_are_counted_without_logging() {
+        let log = DeadLetterLog::default();
+        for i in 0..=RECENT_DEAD_LETTERS {
+            log.record(&message(&format!("topic.{}", i)), None, false);
+        }
+        assert!(log.0.lock().unwrap().logged.is_none());
+        let letters = log.snapshot();
+        assert_eq!(letters.count, RECENT_DEAD_LETTERS as u64 + 1);
+        assert_eq!(letters.recent.len(), RECENT_DEAD_LETTERS);
+        assert_eq!(letters.recent[0].topic, "topic.1");
+        assert_eq!(letters.recent[0].size, 4);
+    }
+}
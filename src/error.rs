@@ -0,0 +1,194 @@
+//! Errors of orchestrator, processes and routing threads
+//!
+//! Failures are returned as `anyhow::Error` wrapping `OrchestratorError`,
+//! which can be inspected with `downcast_ref`:
+//!
+//! ```
+//! use ipc_orchestrator::OrchestratorError;
+//!
+//! let err: anyhow::Error = OrchestratorError::UnroutedTopic {
+//!     bridge: Some("generate".to_owned()),
+//!     topic: "numbers".to_owned(),
+//! }
+//! .into();
+//! match err.downcast_ref::<OrchestratorError>() {
+//!     Some(OrchestratorError::UnroutedTopic { topic, .. }) => assert_eq!(topic, "numbers"),
+//!     _ => unreachable!(),
+//! }
+//! ```
+
+use ipc_channel::ErrorKind;
+use std::fmt;
+use std::io;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+/// Failure of orchestrator carrying names of processes, bridges and topics involved
+#[derive(Debug)]
+pub enum OrchestratorError {
+    /// Process did not pass IPC handshake
+    HandshakeFailed { bridge: String, reason: String },
+    /// Process did not pass readiness probe
+    NotReady { process: String, reason: String },
+    /// Channel of a bridge closed while orchestrator is running
+    ChannelClosed { bridge: String },
+    /// Receiving message failed, bridge is None when it is not known
+    ReceiveFailed {
+        bridge: Option<String>,
+        reason: String,
+    },
+    /// Message sent to topic without recipients
+    UnroutedTopic {
+        bridge: Option<String>,
+        topic: String,
+    },
+    /// Sending message to a bridge failed
+    SendFailed {
+        bridge: String,
+        topic: Option<String>,
+        reason: String,
+    },
+    /// Internal channel between routing threads closed
+    RouterClosed { reason: String },
+    /// Process exited, status is None when it is not known
+    ProcessExited {
+        process: String,
+        status: Option<ExitStatus>,
+    },
+    /// Process was restarted too many times within restart policy window
+    RestartsExhausted {
+        process: String,
+        restarts: usize,
+        window: Duration,
+    },
+    /// Process failed liveness check
+    Unhealthy { process: String, reason: String },
+}
+
+impl OrchestratorError {
+    /// Error of receiving message from `bridge`
+    pub(crate) fn recv(bridge: &str, err: ipc_channel::Error) -> Self {
+        if is_closed(&err) {
+            OrchestratorError::ChannelClosed {
+                bridge: bridge.to_owned(),
+            }
+        } else {
+            OrchestratorError::ReceiveFailed {
+                bridge: Some(bridge.to_owned()),
+                reason: err.to_string(),
+            }
+        }
+    }
+
+    /// Error of sending message of `topic` to `bridge`
+    pub(crate) fn send(bridge: &str, topic: Option<&str>, err: ipc_channel::Error) -> Self {
+        if is_closed(&err) {
+            OrchestratorError::ChannelClosed {
+                bridge: bridge.to_owned(),
+            }
+        } else {
+            OrchestratorError::SendFailed {
+                bridge: bridge.to_owned(),
+                topic: topic.map(str::to_owned),
+                reason: err.to_string(),
+            }
+        }
+    }
+}
+
+/// IPC channel errors caused by the other side closing channel
+fn is_closed(err: &ipc_channel::Error) -> bool {
+    match **err {
+        ErrorKind::Io(ref err) => matches!(
+            err.kind(),
+            io::ErrorKind::BrokenPipe
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::UnexpectedEof
+        ),
+        _ => false,
+    }
+}
+
+impl fmt::Display for OrchestratorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use OrchestratorError::*;
+        match self {
+            HandshakeFailed { bridge, reason } => {
+                write!(
+                    f,
+                    "failed to establish connection from `{}`: {}",
+                    bridge, reason
+                )
+            }
+            NotReady { process, reason } => {
+                write!(f, "process `{}` is not ready: {}", process, reason)
+            }
+            ChannelClosed { bridge } => write!(f, "channel of `{}` closed", bridge),
+            ReceiveFailed {
+                bridge: Some(bridge),
+                reason,
+            } => write!(f, "receiving message from `{}` failed: {}", bridge, reason),
+            ReceiveFailed {
+                bridge: None,
+                reason,
+            } => {
+                write!(f, "receiving message failed: {}", reason)
+            }
+            UnroutedTopic {
+                bridge: Some(bridge),
+                topic,
+            } => write!(
+                f,
+                "received message from `{}` to topic `{}` without recipients",
+                bridge, topic
+            ),
+            UnroutedTopic {
+                bridge: None,
+                topic,
+            } => {
+                write!(
+                    f,
+                    "received message to topic `{}` without recipients",
+                    topic
+                )
+            }
+            SendFailed {
+                bridge,
+                topic: Some(topic),
+                reason,
+            } => write!(
+                f,
+                "sending message from topic `{}` to `{}` failed: {}",
+                topic, bridge, reason
+            ),
+            SendFailed {
+                bridge,
+                topic: None,
+                reason,
+            } => write!(f, "sending message to `{}` failed: {}", bridge, reason),
+            RouterClosed { reason } => write!(f, "router channel closed: {}", reason),
+            ProcessExited {
+                process,
+                status: Some(status),
+            } => write!(f, "process `{}` finish with {}", process, status),
+            ProcessExited {
+                process,
+                status: None,
+            } => write!(f, "process `{}` exit", process),
+            RestartsExhausted {
+                process,
+                restarts,
+                window,
+            } => write!(
+                f,
+                "process `{}` restarted {} times within {:?}",
+                process, restarts, window
+            ),
+            Unhealthy { process, reason } => {
+                write!(f, "process `{}` is unhealthy: {}", process, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OrchestratorError {}
@@ -1,4 +1,5 @@
 #![allow(clippy::unnecessary_mut_passed)]
+#![allow(clippy::upper_case_acronyms)]
 //! Opinionated orchestrator for services which communicate via IPC and are not expected to exit
 //! It allows to start and control processes, handling all the necessary boilerplate:
 //! - Running within async runtime
@@ -7,6 +8,7 @@
 //! - Uses log with info+ levels to
 //! - Uses ipc-channel to establish communication from and to processes
 //! ```
+//! # #[cfg(feature = "orchestrator")] {
 //! use tokio::process::{Command};
 //! use ipc_orchestrator::orchestrator;
 //! // from within async runtime:
@@ -15,19 +17,92 @@
 //!     orchestrator.start("start", &mut Command::new("echo"));
 //!     orchestrator.connect().await
 //! # });
+//! # }
+//! ```
+//!
+//! Child processes only need `client` feature, which leaves out orchestrator dependencies:
+//! ```toml
+//! ipc-orchestrator = { version = "0.3", default-features = false, features = ["client"] }
 //! ```
 
+#[cfg(feature = "orchestrator")]
+mod capture;
 mod channel;
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(feature = "config")]
+pub mod config;
+#[cfg(feature = "orchestrator")]
 mod connected;
+#[cfg(feature = "orchestrator")]
+mod dead_letter;
+#[cfg(feature = "orchestrator")]
+mod error;
+#[cfg(feature = "orchestrator")]
+mod links;
+#[cfg(feature = "orchestrator")]
+mod liveness;
+#[cfg(feature = "orchestrator")]
 mod logger;
 mod macros;
 pub mod message;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "orchestrator")]
 mod orchestrator;
+#[cfg(feature = "orchestrator")]
+mod probe;
+#[cfg(feature = "orchestrator")]
+mod queue;
+#[cfg(feature = "orchestrator")]
+mod restart;
+#[cfg(feature = "orchestrator")]
+mod routes;
+#[cfg(feature = "orchestrator")]
+mod shutdown;
+#[cfg(feature = "orchestrator")]
+mod stats;
+#[cfg(feature = "client")]
+mod topic;
 
+#[cfg(feature = "orchestrator")]
+pub use capture::{CaptureReader, CaptureWriter, Pace, Record};
+#[cfg(feature = "client")]
+pub use client::{
+    connect_ipc_server, connect_links, enable_headers, notify_ready, recv_with_headers, reply,
+    send_with_headers, subscribe, unsubscribe, AsyncClient, Heartbeat, PeerLinks, Request,
+    RequestError,
+};
+#[cfg(feature = "orchestrator")]
+pub use connected::{ConnectedOrchestrator, OrchestratorHandle, RouteTable};
+#[cfg(feature = "orchestrator")]
+pub use dead_letter::{DeadLetter, DeadLetterPolicy, DeadLetters};
+#[cfg(feature = "orchestrator")]
+pub use error::OrchestratorError;
 pub use ipc_channel::ipc::{IpcReceiver, IpcSender};
+#[cfg(feature = "orchestrator")]
+pub use liveness::Liveness;
+#[cfg(feature = "orchestrator")]
+pub use logger::{Output, OutputLines};
+#[cfg(feature = "orchestrator")]
 use tokio::process::Child;
 
+#[cfg(feature = "orchestrator")]
 pub use orchestrator::{orchestrator, Orchestrator};
+#[cfg(feature = "orchestrator")]
+pub use probe::Probe;
+#[cfg(feature = "orchestrator")]
+pub use queue::{Overflow, QueueStats};
+#[cfg(feature = "orchestrator")]
+pub use restart::{Restart, RestartPolicy};
+#[cfg(feature = "orchestrator")]
+pub use routes::RouteChanges;
+#[cfg(feature = "orchestrator")]
+pub use shutdown::ExitReport;
+#[cfg(feature = "orchestrator")]
+pub use stats::{Stats, Traffic};
+#[cfg(feature = "client")]
+pub use topic::TopicPattern;
 
 /// Channel for duplex communication via IPC
 pub type Channel = channel::Channel<message::Message>;
@@ -36,10 +111,12 @@ pub type Sender = IpcSender<message::Message>;
 /// IPC Receiver for Message
 pub type Receiver = IpcReceiver<message::Message>;
 
+#[cfg(feature = "orchestrator")]
 pub struct Process {
     name: String,
     child: Child,
 }
+#[cfg(feature = "orchestrator")]
 impl std::fmt::Debug for Process {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Process {{ {} }}", self.name)
@@ -47,27 +124,17 @@ impl std::fmt::Debug for Process {
 }
 
 /// Communication channel for module `name`
+#[cfg(feature = "orchestrator")]
 #[derive(Debug)]
 pub struct Bridge {
     pub channel: Channel,
     pub name: String,
+    /// Senders to restarted process
+    tx_reconnects: Option<IpcReceiver<Sender>>,
+    /// Receivers from restarted process
+    rx_reconnects: Option<IpcReceiver<Receiver>>,
 }
 
 pub const IPC_SERVER_ENV_VAR: &str = "IPC_SERVER";
-
-/// This is helper function for implementing child processes
-/// Child process will automatically connect to the IPC server
-/// passed in the env var "IPC_SERVER".
-/// This env var is injected by orchestrator.
-/// Execution blocks until connected
-///
-/// TODO: move to separate client library or set features to exclude all the other unnecessary code
-pub fn connect_ipc_server() -> anyhow::Result<Channel> {
-    let ipc_output = std::env::var(IPC_SERVER_ENV_VAR)?;
-    println!("Connecting to server: {}", ipc_output);
-    let tx = IpcSender::connect(ipc_output.clone())?;
-    let (ch1, ch2) = Channel::duplex()?;
-    println!("Connected, sending Channel to server: {}", ipc_output);
-    tx.send(ch1)?;
-    Ok(ch2)
-}
+/// Env var with name of server handing links to process, see `client::connect_links`
+pub const IPC_LINKS_ENV_VAR: &str = "IPC_LINKS";
@@ -0,0 +1,194 @@
+//! Direct peer-to-peer links between processes, bypassing router
+//!
+//! `Orchestrator::link("generate", "sum")` creates IPC channel from `generate` to `sum`,
+//! orchestrator hands its sender to `generate` and its receiver to `sum` during handshake.
+//! Linked process is started with links server name in "IPC_LINKS" env var,
+//! it receives its ends with `connect_links` and is not ready until it did.
+//!
+//! Messages sent over links do not cross orchestrator, hence they are neither routed
+//! nor counted in stats. Orchestrator knows the topology, see `ConnectedOrchestrator::links`,
+//! and supervises linked processes as any other, e.g. with `Liveness` over their bridges.
+//!
+//! Restarted sender receives the same link again, so receiver is not affected by its restarts.
+//! Process which does not connect within `Orchestrator::handshake_timeout` fails with
+//! `OrchestratorError::HandshakeFailed`.
+//! Receiver of a link cannot be restarted, as its peers would keep sending to closed channel.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use tokio::process::Command;
+//! use ipc_orchestrator::{connect_links, orchestrator, message::Message};
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! // Orchestrator
+//! let mut orchestrator = orchestrator().ipc(true);
+//! orchestrator.link("generate", "sum")?;
+//! orchestrator.start("generate", &mut Command::new("generate"))?;
+//! orchestrator.start("sum", &mut Command::new("sum"))?;
+//! let orchestra = orchestrator.connect().await?;
+//! assert_eq!(orchestra.links(), &[("generate".to_owned(), "sum".to_owned())]);
+//!
+//! // Process `generate`
+//! let mut links = connect_links()?;
+//! let sum = links.to.remove("sum").unwrap();
+//! sum.send(Message { topic: "generate".to_owned(), data: vec![1, 2, 3] })?;
+//!
+//! // Process `sum`
+//! let mut links = connect_links()?;
+//! let generate = links.from.remove("generate").unwrap();
+//! let msg = generate.recv()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::PeerLinks;
+use crate::error::OrchestratorError;
+use crate::probe::Probe;
+use crate::IPC_LINKS_ENV_VAR;
+use anyhow::{anyhow, Context};
+use crossbeam::channel;
+use ipc_channel::ipc::{self, IpcOneShotServer, IpcSender};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::process::Command;
+
+/// Default time for process to connect to links server
+pub(crate) const HANDSHAKE_TIMEOUT: Duration = Probe::DEFAULT_TIMEOUT;
+
+/// Server which process connects to with `connect_links`
+pub(crate) struct LinksServer {
+    server: IpcOneShotServer<IpcSender<PeerLinks>>,
+    name: String,
+}
+
+/// Start links server and pass its name to process
+pub(crate) fn serve(cmd: &mut Command) -> anyhow::Result<LinksServer> {
+    let (server, name) =
+        IpcOneShotServer::new().context("Failed to start links IpcOneShotServer")?;
+    cmd.env(IPC_LINKS_ENV_VAR, &name);
+    Ok(LinksServer { server, name })
+}
+
+/// Blocking wait for process to connect within `timeout`, then hand it `links`
+pub(crate) fn accept(
+    server: LinksServer,
+    links: PeerLinks,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let LinksServer { server, name } = server;
+    let tx = accept_within(server, name, timeout, &channel::never(), || {
+        Ok(ipc::channel()?.0)
+    })?;
+    tx.send(links)?;
+    Ok(())
+}
+
+/// Blocking wait for process to connect to `server` named `name` within `timeout`
+/// or until `stop` disconnects. Accept cannot be cancelled,
+/// expired server is unblocked by connecting to it with `dummy` as process would
+pub(crate) fn accept_within<T>(
+    server: IpcOneShotServer<T>,
+    name: String,
+    timeout: Duration,
+    stop: &channel::Receiver<()>,
+    dummy: fn() -> anyhow::Result<T>,
+) -> anyhow::Result<T>
+where
+    T: for<'de> Deserialize<'de> + Serialize + 'static,
+{
+    let (accepted, waiting) = channel::bounded::<()>(1);
+    let stop = stop.clone();
+    let expiring = std::thread::spawn(move || {
+        let expired = crossbeam::select! {
+            recv(waiting) -> _ => None,
+            recv(stop) -> _ => Some("orchestrator stopped".to_owned()),
+            default(timeout) => Some(format!("process did not connect within {:?}", timeout)),
+        };
+        if expired.is_some() {
+            let _ = unblock(name, dummy);
+        }
+        expired
+    });
+    let res = server.accept();
+    let _ = accepted.send(());
+    if let Some(reason) = expiring.join().unwrap_or(None) {
+        return Err(anyhow!(reason));
+    }
+    Ok(res?.1)
+}
+
+/// Connect to server as process would, so that it stops waiting
+fn unblock<T>(name: String, dummy: fn() -> anyhow::Result<T>) -> anyhow::Result<()>
+where
+    T: Serialize,
+{
+    let server = IpcSender::<T>::connect(name)?;
+    server.send(dummy()?)?;
+    Ok(())
+}
+
+/// Hand `links` to process `name` within `timeout` without blocking async runtime
+pub(crate) async fn hand(
+    server: LinksServer,
+    name: String,
+    links: PeerLinks,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let handed = tokio::task::spawn_blocking(move || accept(server, links, timeout)).await?;
+    handed.map_err(|err| {
+        OrchestratorError::HandshakeFailed {
+            bridge: name,
+            reason: format!("handing links: {}", err),
+        }
+        .into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shutdown::Stop;
+    use crate::Channel;
+    use std::time::Instant;
+
+    #[test]
+    fn accept_gives_up_once_stopped() {
+        let (server, name) = IpcOneShotServer::<Channel>::new().unwrap();
+        let (stop_tx, stop) = Stop::new();
+        let start = Instant::now();
+        let stopping = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            drop(stop_tx);
+        });
+        let accepted = accept_within(server, name, HANDSHAKE_TIMEOUT, stop.receiver(), || {
+            Ok(Channel::duplex()?.0)
+        });
+        stopping.join().unwrap();
+        assert_eq!(accepted.unwrap_err().to_string(), "orchestrator stopped");
+        assert!(start.elapsed() < HANDSHAKE_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn peer_which_never_connects_fails_handshake() {
+        let mut cmd = Command::new("true");
+        let server = serve(&mut cmd).unwrap();
+        let links = PeerLinks {
+            to: Default::default(),
+            from: Default::default(),
+        };
+        let timeout = Duration::from_millis(50);
+        let start = Instant::now();
+        let err = hand(server, "sum".to_owned(), links, timeout)
+            .await
+            .unwrap_err();
+        assert!(start.elapsed() < HANDSHAKE_TIMEOUT);
+        match err.downcast_ref::<OrchestratorError>() {
+            Some(OrchestratorError::HandshakeFailed { bridge, reason }) => {
+                assert_eq!(bridge, "sum");
+                assert_eq!(reason, "handing links: process did not connect within 50ms");
+            }
+            _ => panic!("handshake did not fail: {}", err),
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! Liveness monitoring of IPC connected processes
+//!
+//! Process might hang while staying alive with its output open.
+//! Process started with `Liveness` is considered unhealthy when
+//! - it missed configured number of heartbeat intervals, any message counts as heartbeat
+//! - it did not send messages to watched topic for too long
+//!
+//! Unhealthy process is killed, then restarted or reported according to its restart policy.
+//! Messages are observed by routers, hence monitoring starts once process bridge is routed.
+//!
+//! Child process sends heartbeats with `Heartbeat`:
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use ipc_orchestrator::{connect_ipc_server, Heartbeat};
+//!
+//! let (tx, rx) = connect_ipc_server().unwrap().split().unwrap();
+//! let mut heartbeat = Heartbeat::new(tx.clone(), Duration::from_secs(1));
+//! while let Ok(msg) = rx.recv() {
+//!     // process message
+//!     heartbeat.beat().unwrap();
+//! }
+//! ```
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use ipc_orchestrator::{orchestrator, Liveness};
+//!
+//! let mut orchestrator = orchestrator().ipc(true);
+//! let liveness = Liveness::heartbeat(Duration::from_secs(1), 3)
+//!     .watch_topic("sum", Duration::from_secs(10));
+//! orchestrator.liveness("sum", liveness).unwrap();
+//! ```
+
+use crate::message::Message;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Conditions of process being alive
+#[derive(Clone, Debug, Default)]
+pub struct Liveness {
+    heartbeat: Option<(Duration, u32)>,
+    watch: Vec<(String, Duration)>,
+}
+
+impl Liveness {
+    /// Process should send message at least once per `interval`,
+    /// it is unhealthy after `missed` intervals without messages
+    pub fn heartbeat(interval: Duration, missed: u32) -> Self {
+        Self {
+            heartbeat: Some((interval, missed.max(1))),
+            watch: Vec::new(),
+        }
+    }
+
+    /// Process is unhealthy when it did not send messages to `topic` within `quiet` period
+    pub fn watch_topic(mut self, topic: &str, quiet: Duration) -> Self {
+        self.watch.push((topic.to_owned(), quiet));
+        self
+    }
+
+    /// How often liveness is checked
+    pub(crate) fn check_interval(&self) -> Duration {
+        self.heartbeat
+            .iter()
+            .map(|(interval, _)| *interval)
+            .chain(self.watch.iter().map(|(_, quiet)| *quiet))
+            .min()
+            .unwrap_or_else(|| Duration::from_secs(1))
+    }
+
+    /// Reason of process being unhealthy at `now`
+    pub(crate) fn unhealthy(&self, activity: &Activity, now: Instant) -> Option<String> {
+        if !activity.armed {
+            return None;
+        }
+        let elapsed = |since: &Instant| now.saturating_duration_since(*since);
+        if let Some((interval, missed)) = self.heartbeat {
+            if elapsed(&activity.heartbeat) > interval * missed {
+                return Some(format!("missed {} heartbeats of {:?}", missed, interval));
+            }
+        }
+        self.watch.iter().find_map(|(topic, quiet)| {
+            activity
+                .topics
+                .get(topic)
+                .filter(|seen| elapsed(seen) > *quiet)
+                .map(|_| format!("no messages to topic `{}` within {:?}", topic, quiet))
+        })
+    }
+}
+
+/// Last messages received from process
+#[derive(Debug)]
+pub(crate) struct Activity {
+    /// Process is monitored once its messages are observed by router
+    armed: bool,
+    heartbeat: Instant,
+    topics: HashMap<String, Instant>,
+}
+
+impl Activity {
+    pub fn new(liveness: &Liveness, now: Instant) -> Self {
+        Self {
+            armed: false,
+            heartbeat: now,
+            topics: liveness
+                .watch
+                .iter()
+                .map(|(topic, _)| (topic.clone(), now))
+                .collect(),
+        }
+    }
+
+    /// Start monitoring from `now`, e.g. once router started or process restarted
+    pub fn reset(&mut self, armed: bool, now: Instant) {
+        self.armed |= armed;
+        self.heartbeat = now;
+        for seen in self.topics.values_mut() {
+            *seen = now;
+        }
+    }
+
+    /// Record message received from process at `now`
+    pub fn seen(&mut self, msg: &Message, now: Instant) {
+        self.heartbeat = now;
+        if let Some(seen) = self.topics.get_mut(&msg.topic) {
+            *seen = now;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(topic: &str) -> Message {
+        Message {
+            topic: topic.to_owned(),
+            data: Vec::new(),
+        }
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn missed_heartbeats_make_process_unhealthy() {
+        let liveness = Liveness::heartbeat(secs(1), 3);
+        let start = Instant::now();
+        let mut activity = Activity::new(&liveness, start);
+        // Process is not monitored before its messages are observed
+        assert_eq!(liveness.unhealthy(&activity, start + secs(10)), None);
+
+        activity.reset(true, start);
+        assert_eq!(liveness.unhealthy(&activity, start + secs(3)), None);
+        activity.seen(&message("any"), start + secs(3));
+        assert_eq!(liveness.unhealthy(&activity, start + secs(6)), None);
+        assert_eq!(
+            liveness.unhealthy(&activity, start + secs(7)).as_deref(),
+            Some("missed 3 heartbeats of 1s")
+        );
+        // Timestamps recorded after check do not underflow
+        assert_eq!(liveness.unhealthy(&activity, start), None);
+    }
+
+    #[test]
+    fn watched_topic_keeps_process_alive() {
+        let liveness = Liveness::heartbeat(secs(1), 1).watch_topic("sum", secs(5));
+        let start = Instant::now();
+        let mut activity = Activity::new(&liveness, start);
+        activity.reset(true, start);
+        for at in 1..=10 {
+            activity.seen(&message("heartbeat"), start + secs(at));
+            if at % 4 == 0 {
+                activity.seen(&message("sum"), start + secs(at));
+            }
+            assert_eq!(liveness.unhealthy(&activity, start + secs(at)), None);
+        }
+        // Heartbeats do not count as messages to watched topic
+        activity.seen(&message("heartbeat"), start + secs(14));
+        assert_eq!(
+            liveness.unhealthy(&activity, start + secs(14)).as_deref(),
+            Some("no messages to topic `sum` within 5s")
+        );
+        activity.seen(&message("sum"), start + secs(14));
+        assert_eq!(liveness.unhealthy(&activity, start + secs(14)), None);
+    }
+
+    #[test]
+    fn check_interval_is_the_shortest_period() {
+        let liveness = Liveness::heartbeat(secs(2), 3).watch_topic("sum", secs(1));
+        assert_eq!(liveness.check_interval(), secs(1));
+        assert_eq!(Liveness::default().check_interval(), secs(1));
+    }
+}
@@ -1,20 +1,145 @@
 use anyhow::anyhow;
-use futures::future::Future;
-use log::info;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::ChildStdout;
+use futures::channel::oneshot;
+use futures::future::{Future, FutureExt};
+use futures::{pin_mut, select};
+use log::{info, warn};
+use regex::Regex;
+use tokio::io::{AsyncBufReadExt, BufReader, Lines};
+use tokio::process::{ChildStderr, ChildStdout};
+
+/// Output stream of a child process
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Output {
+    Stdout,
+    Stderr,
+}
+
+/// Lines from child process stdout, stderr or both when stderr is merged into stdout handler
+pub struct OutputLines {
+    stdout: Option<Lines<BufReader<ChildStdout>>>,
+    stderr: Option<Lines<BufReader<ChildStderr>>>,
+    /// Readiness probes waiting for stdout line
+    probes: Vec<(Regex, oneshot::Sender<()>)>,
+}
+
+impl OutputLines {
+    pub(crate) fn new(
+        stdout: Option<ChildStdout>,
+        stderr: Option<ChildStderr>,
+        probes: Vec<(Regex, oneshot::Sender<()>)>,
+    ) -> Self {
+        Self {
+            stdout: stdout.map(|s| BufReader::new(s).lines()),
+            stderr: stderr.map(|s| BufReader::new(s).lines()),
+            probes,
+        }
+    }
+
+    /// Read next line together with the stream it came from
+    /// Returns None when all the streams closed
+    pub async fn next_line(&mut self) -> std::io::Result<Option<(Output, String)>> {
+        let line = self.read_line().await?;
+        if let Some((Output::Stdout, line)) = line.as_ref() {
+            if !self.probes.is_empty() {
+                let (passed, probes) = self
+                    .probes
+                    .drain(..)
+                    .partition::<Vec<_>, _>(|(regex, _)| regex.is_match(line));
+                self.probes = probes;
+                for (_, tx) in passed {
+                    tx.send(()).ok();
+                }
+            }
+        }
+        Ok(line)
+    }
+
+    async fn read_line(&mut self) -> std::io::Result<Option<(Output, String)>> {
+        loop {
+            let closed = match (self.stdout.as_mut(), self.stderr.as_mut()) {
+                (None, None) => return Ok(None),
+                (Some(out), None) => {
+                    return Ok(out.next_line().await?.map(|l| (Output::Stdout, l)));
+                }
+                (None, Some(err)) => {
+                    return Ok(err.next_line().await?.map(|l| (Output::Stderr, l)));
+                }
+                (Some(out), Some(err)) => {
+                    let out = out.next_line().fuse();
+                    let err = err.next_line().fuse();
+                    pin_mut!(out, err);
+                    // Lines keep partially read line between polls,
+                    // hence it is safe to drop pending read
+                    select!(
+                        line = out => match line? {
+                            Some(line) => return Ok(Some((Output::Stdout, line))),
+                            None => Output::Stdout,
+                        },
+                        line = err => match line? {
+                            Some(line) => return Ok(Some((Output::Stderr, line))),
+                            None => Output::Stderr,
+                        },
+                    )
+                }
+            };
+            match closed {
+                Output::Stdout => self.stdout = None,
+                Output::Stderr => self.stderr = None,
+            }
+        }
+    }
+}
 
 /// Creates default log handler
-/// Default log handler will read lines from process stdout
-/// and log them with info level adding process name
-pub fn default_log_handler(c: ChildStdout, s: String) -> impl Future<Output = anyhow::Result<()>> {
+/// Default log handler will read lines from process output
+/// and log them adding process name: stdout with info level, stderr with warn level
+pub fn default_log_handler(c: OutputLines, s: String) -> impl Future<Output = anyhow::Result<()>> {
     log_handler(c, s)
 }
 
-async fn log_handler(reader: ChildStdout, name: String) -> anyhow::Result<()> {
-    let mut reader = BufReader::new(reader).lines();
-    while let Some(line) = reader.next_line().await? {
-        info!(target: &name, "{}", line);
+async fn log_handler(mut reader: OutputLines, name: String) -> anyhow::Result<()> {
+    while let Some((output, line)) = reader.next_line().await? {
+        match output {
+            Output::Stdout => info!(target: &name, "{}", line),
+            Output::Stderr => warn!(target: &name, "{}", line),
+        }
     }
     Err(anyhow!("runtime `{}` closed its output", name))
 }
+
+/// Take child output streams and pass them to log handlers,
+/// stderr gets its own handler unless it is merged into stdout handler.
+/// Output line readiness `probes` are matched against stdout
+pub(crate) fn output_loggers<LF>(
+    logger: fn(OutputLines, String) -> LF,
+    child: &mut tokio::process::Child,
+    name: &str,
+    merge_stderr: bool,
+    probes: Vec<(Regex, oneshot::Sender<()>)>,
+) -> anyhow::Result<Vec<LF>> {
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("child did not provide a handle to stdout"))?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| anyhow!("child did not provide a handle to stderr"))?;
+    Ok(if merge_stderr {
+        vec![logger(
+            OutputLines::new(Some(stdout), Some(stderr), probes),
+            name.to_owned(),
+        )]
+    } else {
+        vec![
+            logger(
+                OutputLines::new(Some(stdout), None, probes),
+                name.to_owned(),
+            ),
+            logger(
+                OutputLines::new(None, Some(stderr), Vec::new()),
+                name.to_owned(),
+            ),
+        ]
+    })
+}
@@ -6,8 +6,31 @@
 //! use ipc_orchestrator::message::Message;
 //! let msg = Message { topic: "my_topic".to_owned(), data: vec![1,2,3,4] };
 //! ```
+//!
+//! Topics starting with `orchestrator.` are reserved for control messages
+//! between orchestrator and processes, such messages are not routed.
+//!
+//! # Headers
+//!
+//! Message might be sent with optional `Headers` wrapped into a single envelope message,
+//! so that producers building plain `Message { topic, data }` keep working
+//! and messages of senders sharing a channel cannot interleave with headers.
+//! Router stamps source bridge, sequence number and timestamp of every message and sends
+//! envelopes to processes which asked for them with `Message::headers_on()`,
+//! see `client::recv_with_headers` and `client::send_with_headers`.
+//! ```
+//! use ipc_orchestrator::message::{Headers, Message};
+//! let headers = Headers { correlation_id: Some(42), ..Default::default() };
+//! let msg = Message { topic: "my_topic".to_owned(), data: vec![1, 2] };
+//! let envelope = msg.with_headers(&headers);
+//! assert!(envelope.is_control());
+//! let (msg, received) = envelope.split_headers();
+//! assert_eq!(msg.topic, "my_topic");
+//! assert_eq!(received, Some(headers));
+//! ```
 
 use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Message {
@@ -16,3 +39,118 @@ pub struct Message {
     #[serde(with = "serde_bytes")]
     pub data: Vec<u8>,
 }
+
+/// Prefix of topics reserved for control messages
+pub const CONTROL_PREFIX: &str = "orchestrator.";
+/// Topic of message which process sends to report it is ready
+pub const READY_TOPIC: &str = "orchestrator.ready";
+/// Topic of message which process sends to report it is alive
+pub const HEARTBEAT_TOPIC: &str = "orchestrator.heartbeat";
+/// Topic of envelope carrying message with its headers
+pub const HEADERS_TOPIC: &str = "orchestrator.headers";
+/// Topic of message which process sends to receive messages with headers
+pub const HEADERS_ON_TOPIC: &str = "orchestrator.headers.on";
+/// Topic of reply to request, router delivers it to requester only
+pub const REPLY_TOPIC: &str = "orchestrator.reply";
+/// Topic of message router sends to requester when request topic has no recipients
+pub const NO_RESPONDER_TOPIC: &str = "orchestrator.no_responder";
+/// Topic of message which process sends to have topic routed to it, data is topic or pattern
+pub const SUBSCRIBE_TOPIC: &str = "orchestrator.subscribe";
+/// Topic of message which process sends to stop topic being routed to it
+pub const UNSUBSCRIBE_TOPIC: &str = "orchestrator.unsubscribe";
+
+/// Optional headers of message, sent in envelope with message, see `Message::with_headers`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Headers {
+    /// Bridge message was received from, stamped by router
+    pub source: Option<String>,
+    /// Number of message among messages of its sender starting from 1,
+    /// stamped by router unless set by sender
+    pub sequence: Option<u64>,
+    /// Time message was sent, stamped by router unless set by sender
+    pub timestamp: Option<SystemTime>,
+    /// Id correlating message with other messages, e.g. request with its response
+    pub correlation_id: Option<u64>,
+    /// Message is a request, recipient should reply with its correlation id
+    pub request: bool,
+}
+
+impl Message {
+    /// Envelope carrying message with `headers`, sent as a single message
+    pub fn with_headers(&self, headers: &Headers) -> Message {
+        Message {
+            topic: HEADERS_TOPIC.to_owned(),
+            data: bincode::serialize(&(headers, self)).expect("message is serializable"),
+        }
+    }
+
+    /// Message and its headers carried by envelope,
+    /// other messages are returned as they are without headers
+    pub fn split_headers(self) -> (Message, Option<Headers>) {
+        if self.topic != HEADERS_TOPIC {
+            return (self, None);
+        }
+        match bincode::deserialize(&self.data) {
+            Ok((headers, msg)) => (msg, Some(headers)),
+            Err(_) => (self, None),
+        }
+    }
+
+    /// Message reporting that process is ready, see `Probe::ready_message`
+    pub fn ready() -> Self {
+        Message {
+            topic: READY_TOPIC.to_owned(),
+            data: Vec::new(),
+        }
+    }
+
+    /// Message reporting that process is alive, see `Heartbeat`
+    pub fn heartbeat() -> Self {
+        Message {
+            topic: HEARTBEAT_TOPIC.to_owned(),
+            data: Vec::new(),
+        }
+    }
+
+    /// Message asking orchestrator to send messages to process in envelopes with headers
+    pub fn headers_on() -> Self {
+        Message {
+            topic: HEADERS_ON_TOPIC.to_owned(),
+            data: Vec::new(),
+        }
+    }
+
+    /// Message asking router to route `topic` or pattern like `metrics.*` to process
+    pub fn subscribe(topic: &str) -> Self {
+        Message {
+            topic: SUBSCRIBE_TOPIC.to_owned(),
+            data: topic.as_bytes().to_vec(),
+        }
+    }
+
+    /// Message asking router to stop routing `topic` or pattern to process
+    pub fn unsubscribe(topic: &str) -> Self {
+        Message {
+            topic: UNSUBSCRIBE_TOPIC.to_owned(),
+            data: topic.as_bytes().to_vec(),
+        }
+    }
+
+    /// Reply to request with `headers`, see `client::reply`
+    pub fn is_reply(&self) -> bool {
+        self.topic == REPLY_TOPIC
+    }
+
+    /// Control message handled by router: reply, subscribe or unsubscribe
+    pub fn is_for_router(&self) -> bool {
+        matches!(
+            self.topic.as_str(),
+            REPLY_TOPIC | SUBSCRIBE_TOPIC | UNSUBSCRIBE_TOPIC
+        )
+    }
+
+    /// Message to orchestrator itself which is not routed to other processes
+    pub fn is_control(&self) -> bool {
+        self.topic.starts_with(CONTROL_PREFIX)
+    }
+}
@@ -0,0 +1,295 @@
+//! Prometheus metrics endpoint
+//!
+//! `ConnectedOrchestrator::serve_metrics(addr)` starts HTTP listener serving metrics
+//! in Prometheus text format at `/metrics`:
+//! - `ipc_orchestrator_process_up`, `ipc_orchestrator_process_restarts_total`
+//!   and `ipc_orchestrator_process_exit_code` per process and in-process participant,
+//!   including processes spawned after metrics were served
+//! - `ipc_orchestrator_topic_messages_total` and `ipc_orchestrator_topic_bytes_total` per topic
+//! - received and sent messages, bytes and send errors per bridge
+//! - `ipc_orchestrator_queue_depth` and dropped messages per recipient queue
+//! - `ipc_orchestrator_dead_letters_total`
+//!
+//! Listener is meant for local scraping, it serves one request at a time
+//! and stops when orchestrator shuts down. Available with `metrics` feature.
+//!
+//! # Example
+//!
+//! ```
+//! use std::io::{Read, Write};
+//! use std::net::TcpStream;
+//! use std::time::Duration;
+//! use tokio::process::Command;
+//! use ipc_orchestrator::orchestrator;
+//!
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//!     let mut orchestrator = orchestrator().ipc(false);
+//!     orchestrator.start("sleep", Command::new("sleep").arg("10")).unwrap();
+//!     let mut orchestra = orchestrator.connect().await.unwrap();
+//!     let addr = orchestra.serve_metrics("127.0.0.1:0").unwrap();
+//!     // Processes spawned later and participants are served as well
+//!     let mut cmd = Command::new("sleep");
+//!     cmd.arg("10");
+//!     orchestra.spawn("later", cmd).await.unwrap();
+//!     let _channel = orchestra.participant("host").unwrap();
+//!
+//!     let mut stream = TcpStream::connect(addr).unwrap();
+//!     stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+//!     let mut response = String::new();
+//!     stream.read_to_string(&mut response).unwrap();
+//!     assert!(response.contains("ipc_orchestrator_process_up{process=\"sleep\"} 1"));
+//!     assert!(response.contains("ipc_orchestrator_process_up{process=\"later\"} 1"));
+//!     assert!(response.contains("ipc_orchestrator_process_up{process=\"host\"} 1"));
+//!     # orchestra.shutdown(Duration::from_secs(1)).await.unwrap();
+//! # });
+//! ```
+
+use crate::restart::LiveStates;
+use crate::shutdown::Stop;
+use crate::stats::{Registry, Traffic};
+use log::{info, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Write as _};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::unix::process::ExitStatusExt;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+/// How often listener checks for connections and orchestrator stop
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+/// Time given to client to send request and receive response
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sources of metrics
+pub(crate) struct Metrics {
+    pub states: LiveStates,
+    pub stats: Registry,
+}
+
+/// Bind listener to `addr` and serve metrics from background thread until orchestrator stops
+pub(crate) fn serve(addr: &str, metrics: Metrics, stop: Stop) -> anyhow::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+    info!("serving metrics at http://{}/metrics", addr);
+    std::thread::spawn(move || {
+        while !stop.is_stopping() {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(err) = respond(stream, &metrics) {
+                        warn!("serving metrics failed: {}", err);
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(ACCEPT_INTERVAL)
+                }
+                Err(err) => warn!("accepting metrics connection failed: {}", err),
+            }
+        }
+    });
+    Ok(addr)
+}
+
+fn respond(mut stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    // Only request line is of interest, headers are read to not reset connection
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.ends_with(b"\r\n\r\n") && request.len() < 8192 {
+        match stream.read(&mut buf)? {
+            0 => break,
+            n => request.extend_from_slice(&buf[..n]),
+        }
+    }
+    let request = String::from_utf8_lossy(&request);
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+    let (status, body) = match path {
+        "/metrics" | "/" => ("200 OK", metrics.render()),
+        _ => ("404 Not Found", "not found\n".to_owned()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+impl Metrics {
+    /// Metrics in Prometheus text format
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let live = self.states.read().unwrap();
+        let states: BTreeMap<_, _> = live.iter().collect();
+        let stats = self.stats.snapshot();
+
+        let up = states
+            .iter()
+            .map(|(name, state)| (*name, state.is_running() as u8));
+        family(
+            &mut out,
+            "process_up",
+            "gauge",
+            "Process is running",
+            "process",
+            up,
+        );
+        let restarts = states
+            .iter()
+            .map(|(name, state)| (*name, state.restarts.load(Ordering::Relaxed)));
+        let help = "Restarts of process";
+        family(
+            &mut out,
+            "process_restarts_total",
+            "counter",
+            help,
+            "process",
+            restarts,
+        );
+        let exit_codes = states.iter().filter_map(|(name, state)| {
+            let status = (*state.exit.lock().unwrap())?;
+            let code = status.code().or_else(|| status.signal().map(|s| 128 + s))?;
+            Some((*name, code))
+        });
+        let help = "Exit code of the last process exit, 128 + signal when killed by signal";
+        family(
+            &mut out,
+            "process_exit_code",
+            "gauge",
+            help,
+            "process",
+            exit_codes,
+        );
+
+        traffic(
+            &mut out,
+            "topic",
+            "topic",
+            "routed per topic",
+            &stats.topics,
+        );
+        traffic(
+            &mut out,
+            "bridge_received",
+            "bridge",
+            "received from bridge",
+            &stats.sources,
+        );
+        traffic(
+            &mut out,
+            "bridge_sent",
+            "bridge",
+            "sent to bridge",
+            &stats.destinations,
+        );
+        let errors = sorted(&stats.destinations).map(|(name, t)| (name, t.errors));
+        let help = "Failures of sending messages to bridge";
+        family(
+            &mut out,
+            "bridge_send_errors_total",
+            "counter",
+            help,
+            "bridge",
+            errors,
+        );
+
+        let depth = sorted(&stats.queues).map(|(name, q)| (name, q.depth));
+        let help = "Messages waiting in recipient queue";
+        family(&mut out, "queue_depth", "gauge", help, "bridge", depth);
+        let capacity = sorted(&stats.queues).map(|(name, q)| (name, q.capacity));
+        let help = "Capacity of recipient queue";
+        family(
+            &mut out,
+            "queue_capacity",
+            "gauge",
+            help,
+            "bridge",
+            capacity,
+        );
+        let dropped = sorted(&stats.queues).map(|(name, q)| (name, q.dropped));
+        let help = "Messages dropped by recipient queue";
+        family(
+            &mut out,
+            "queue_dropped_total",
+            "counter",
+            help,
+            "bridge",
+            dropped,
+        );
+
+        let help = "Messages to topics without recipients";
+        header(&mut out, "dead_letters_total", "counter", help);
+        let _ = writeln!(
+            out,
+            "ipc_orchestrator_dead_letters_total {}",
+            stats.dead_letters
+        );
+        out
+    }
+}
+
+fn sorted<V>(map: &HashMap<String, V>) -> impl Iterator<Item = (&String, &V)> {
+    map.iter().collect::<BTreeMap<_, _>>().into_iter()
+}
+
+fn header(out: &mut String, metric: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP ipc_orchestrator_{} {}", metric, help);
+    let _ = writeln!(out, "# TYPE ipc_orchestrator_{} {}", metric, kind);
+}
+
+/// Metric family with samples labelled by `label`
+fn family<'a, V: Display>(
+    out: &mut String,
+    metric: &str,
+    kind: &str,
+    help: &str,
+    label: &str,
+    samples: impl Iterator<Item = (&'a String, V)>,
+) {
+    header(out, metric, kind, help);
+    for (name, value) in samples {
+        let name = name
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        let _ = writeln!(
+            out,
+            "ipc_orchestrator_{}{{{}=\"{}\"}} {}",
+            metric, label, name, value
+        );
+    }
+}
+
+/// Messages and bytes counters of `traffic`
+fn traffic(
+    out: &mut String,
+    prefix: &str,
+    label: &str,
+    desc: &str,
+    traffic: &HashMap<String, Traffic>,
+) {
+    let messages = sorted(traffic).map(|(name, t)| (name, t.messages));
+    let help = format!("Messages {}", desc);
+    family(
+        out,
+        &format!("{}_messages_total", prefix),
+        "counter",
+        &help,
+        label,
+        messages,
+    );
+    let bytes = sorted(traffic).map(|(name, t)| (name, t.bytes));
+    let help = format!("Bytes of message data {}", desc);
+    family(
+        out,
+        &format!("{}_bytes_total", prefix),
+        "counter",
+        &help,
+        label,
+        bytes,
+    );
+}
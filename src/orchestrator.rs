@@ -13,20 +13,21 @@
 //!
 //! # Example
 //!
-//! This example shows how Orchestrator allows to add custom handlers for process stdout:
+//! This example shows how Orchestrator allows to add custom handlers for process output:
 //!
 //! ```
-//! use tokio::process::{Command, ChildStdout};
-//! use ipc_orchestrator::Orchestrator;
+//! use tokio::process::Command;
+//! use ipc_orchestrator::{Orchestrator, Output, OutputLines};
 //!
 //! use std::sync::atomic::{AtomicBool, Ordering};
 //! static CALLED: AtomicBool = AtomicBool::new(false);
 //!
-//! use tokio::io::{AsyncBufReadExt, BufReader};
-//! async fn mock_log_handler(reader: ChildStdout, name: String) -> anyhow::Result<()> {
-//!    let mut reader = BufReader::new(reader).lines();
-//!    assert_eq!(reader.next_line().await?.unwrap(), "testbed");
-//!    CALLED.store(true, Ordering::Relaxed);
+//! async fn mock_log_handler(mut reader: OutputLines, name: String) -> anyhow::Result<()> {
+//!    if let Some((output, line)) = reader.next_line().await? {
+//!        assert_eq!(output, Output::Stdout);
+//!        assert_eq!(line, "testbed");
+//!        CALLED.store(true, Ordering::Relaxed);
+//!    }
 //!    Ok(())
 //! }
 //!
@@ -44,29 +45,44 @@
 //! # });
 //! ```
 
+use crate::client::PeerLinks;
 use crate::connected::ConnectedOrchestrator;
-use crate::logger::default_log_handler;
-use crate::should_not_complete;
-use crate::{Bridge, Channel, Process};
+use crate::error::OrchestratorError;
+use crate::links;
+use crate::liveness::Liveness;
+use crate::logger::{default_log_handler, output_loggers, OutputLines};
+use crate::message::{Message, READY_TOPIC, SUBSCRIBE_TOPIC, UNSUBSCRIBE_TOPIC};
+use crate::probe::{Prepared, Probe};
+use crate::restart::{ProcessState, Reconnector, Respawn, Restart, RestartPolicy, Supervisor};
+use crate::shutdown::Stop;
+use crate::{Bridge, Channel, Process, Receiver, Sender};
 use anyhow::{anyhow, Context};
-use futures::future::{try_join_all, Fuse, Future, FutureExt, TryFuture, TryJoinAll};
-use futures::{pin_mut, select};
-use ipc_channel::ipc::IpcOneShotServer;
-use log::{debug, error, info, warn};
-use std::collections::HashMap;
+use crossbeam::channel;
+use futures::future::{try_join, try_join_all, Future, TryFuture};
+use futures::select;
+use futures::stream::{FuturesUnordered, StreamExt};
+use ipc_channel::ipc::{self, IpcOneShotServer, IpcReceiver};
+use log::{debug, error, info};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
 use std::pin::Pin;
 use std::process::Stdio;
-use tokio::process::ChildStdout;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::process::Command;
 
-type BFR<R> = Pin<Box<dyn Future<Output = anyhow::Result<R>>>>;
+/// How often ready message probe checks for message from process
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+pub(crate) type BFR<R> = Pin<Box<dyn Future<Output = anyhow::Result<R>>>>;
 
 /// Create default orchestrator
 ///
 /// Default orchestrator comes with `default_log_handler`
 ///
-/// Default log handler will read lines from process stdout
-/// and log them with info level adding process name
+/// Default log handler will read lines from process stdout and stderr
+/// and log them with info and warn levels respectively adding process name
 pub fn orchestrator() -> Orchestrator<impl Future<Output = anyhow::Result<()>>> {
     Orchestrator::from_handlers(default_log_handler)
 }
@@ -74,52 +90,293 @@ pub fn orchestrator() -> Orchestrator<impl Future<Output = anyhow::Result<()>>>
 /// Orchestrator which is in progress of starting up
 pub struct Orchestrator<LF: TryFuture> {
     pub processes: HashMap<String, Process>,
-    loggers: Vec<LF>,
-    bridges: Vec<BFR<Bridge>>,
+    supervisors: HashMap<String, Supervisor<LF>>,
+    /// Readiness of started processes: IPC connection and probes
+    readiness: Vec<BFR<Ready>>,
+    probes: HashMap<String, Vec<Probe>>,
+    liveness: HashMap<String, Liveness>,
+    /// Processes waiting for their dependencies to get ready
+    deferred: VecDeque<Deferred>,
+    dependencies: HashMap<String, Vec<String>>,
+    /// Link ends of processes which are not started yet
+    links: HashMap<String, PeerLinks>,
+    /// Declared links as `(from, to)` pairs
+    topology: Vec<(String, String)>,
     ipc: bool,
     rust_backtrace: bool,
-    logger: fn(ChildStdout, String) -> LF,
+    merge_stderr: bool,
+    /// Time for restarted and linked processes to connect
+    handshake_timeout: Duration,
+    logger: fn(OutputLines, String) -> LF,
+    stop: (channel::Sender<()>, Stop),
+}
+
+/// Process which is started once its dependencies are ready
+struct Deferred {
+    name: String,
+    cmd: Command,
+    policy: RestartPolicy,
+    ipc: bool,
+}
+
+/// Process which passed its readiness probes, with its bridge if IPC is used
+pub(crate) struct Ready {
+    pub name: String,
+    pub bridge: Option<Bridge>,
+}
+
+/// Process started after orchestrator connected, see `ConnectedOrchestrator::spawn`
+pub(crate) struct Spawned {
+    pub state: Arc<ProcessState>,
+    /// Supervision of process, completes once process exits and is not restarted
+    pub process: BFR<()>,
+    pub ready: BFR<Ready>,
+}
+
+/// Starts processes for connected orchestrator, which does not know type of log handler
+pub(crate) trait Spawner {
+    fn spawn(&mut self, name: &str, cmd: Command, policy: RestartPolicy)
+        -> anyhow::Result<Spawned>;
+}
+
+/// Processes under supervision while orchestrator is connecting
+struct Startup {
+    states: HashMap<String, Arc<ProcessState>>,
+    processes: FuturesUnordered<BFR<()>>,
+    readiness: FuturesUnordered<BFR<Ready>>,
+    ready: HashSet<String>,
 }
 
 impl<LF: TryFuture> Orchestrator<LF> {
     /// Create orchestrator with provided log handler
     ///
-    /// Log handler is a function: `fn(ChildStdout, String) -> impl TryFuture`
-    /// Provided future should process ChildStdout until eof,
-    /// returning with anyhow::Result<()>
-    pub fn from_handlers(logger: fn(ChildStdout, String) -> LF) -> Self {
+    /// Log handler is a function: `fn(OutputLines, String) -> impl TryFuture`
+    /// Provided future should process OutputLines until eof,
+    /// returning with anyhow::Result<()>.
+    /// Handler is started separately for stdout and stderr of every process,
+    /// unless stderr is merged into stdout handler with `merge_stderr(true)`
+    pub fn from_handlers(logger: fn(OutputLines, String) -> LF) -> Self {
         Self {
             processes: HashMap::new(),
-            loggers: Vec::new(),
-            bridges: Vec::new(),
+            supervisors: HashMap::new(),
+            readiness: Vec::new(),
+            probes: HashMap::new(),
+            liveness: HashMap::new(),
+            deferred: VecDeque::new(),
+            dependencies: HashMap::new(),
+            links: HashMap::new(),
+            topology: Vec::new(),
             ipc: false,
             rust_backtrace: false,
+            merge_stderr: false,
+            handshake_timeout: links::HANDSHAKE_TIMEOUT,
             logger,
+            stop: Stop::new(),
         }
     }
 }
 
 impl<LF> Orchestrator<LF>
 where
-    LF: Future<Output = anyhow::Result<()>>,
+    LF: Future<Output = anyhow::Result<()>> + 'static,
 {
     /// Start provided command with communication channel
     /// As opinionated executor for all the processes Orchestrator provides following setup:
     /// 1. Start IpcOneShotServer and provide server name to process via
-    /// commandline argument `--orchestrator-ch`
+    ///    commandline argument `--orchestrator-ch`
     /// 2. cmd.kill_on_drop(true) - process will exit if orchestrator's handle is dropped
     /// 3. cmd.stdout(Stdio::piped()) - stdout will be logged as info!(target: &name, ...)
+    /// 4. cmd.stderr(Stdio::piped()) - stderr will be logged as warn!(target: &name, ...)
     pub fn start(&mut self, name: &str, cmd: &mut Command) -> anyhow::Result<()> {
+        self.spawn(name, cmd, None)
+    }
+
+    /// Start provided command same way as `start` does,
+    /// when process exits it will be restarted according to provided policy.
+    ///
+    /// Restarted process is provided with fresh IPC server and output loggers,
+    /// its new channel replaces old one in pipes and routes
+    pub fn start_with_policy(
+        &mut self,
+        name: &str,
+        mut cmd: Command,
+        policy: RestartPolicy,
+    ) -> anyhow::Result<()> {
+        if policy.restart() == Restart::Never {
+            return self.spawn(name, &mut cmd, None);
+        }
+        if self
+            .links
+            .get(name)
+            .is_some_and(|links| !links.from.is_empty())
+        {
+            return Err(anyhow!(
+                "process `{}` receives peer links, it cannot be restarted",
+                name
+            ));
+        }
+        // Restarted process receives the same senders again
+        let links = self.links.get(name).map(|links| links.to.clone());
+        let reconnector = if self.ipc {
+            let (reconnector, tx_feed, rx_feed) = Reconnector::new()?;
+            self.spawn(name, &mut cmd, Some((tx_feed, rx_feed)))?;
+            Some(reconnector)
+        } else {
+            self.spawn(name, &mut cmd, None)?;
+            None
+        };
+        let supervisor = self
+            .supervisors
+            .get_mut(name)
+            .ok_or_else(|| anyhow!("process named `{}` was not started", name))?;
+        supervisor.respawn = Some(Respawn {
+            cmd,
+            policy,
+            logger: self.logger,
+            merge_stderr: self.merge_stderr,
+            handshake_timeout: self.handshake_timeout,
+            reconnector,
+            links,
+        });
+        Ok(())
+    }
+
+    /// Add readiness probe to process `name`, probes should be added before process starts.
+    /// Process is ready when it connected via IPC (if IPC is used) and all its probes passed,
+    /// see `Probe`
+    pub fn probe(&mut self, name: &str, probe: Probe) -> anyhow::Result<()> {
+        if self.processes.contains_key(name) {
+            return Err(anyhow!(
+                "process `{}` already started, probes should be added before start",
+                name
+            ));
+        }
+        self.probes.entry(name.to_owned()).or_default().push(probe);
+        Ok(())
+    }
+
+    /// Monitor liveness of process `name`, it should be set before process starts.
+    /// Unhealthy process is killed, then restarted or reported according to its restart policy,
+    /// see `Liveness`
+    pub fn liveness(&mut self, name: &str, liveness: Liveness) -> anyhow::Result<()> {
         if self.processes.contains_key(name) {
+            return Err(anyhow!(
+                "process `{}` already started, liveness should be set before start",
+                name
+            ));
+        }
+        self.liveness.insert(name.to_owned(), liveness);
+        Ok(())
+    }
+
+    /// Start provided command same way as `start_with_policy` does,
+    /// once all the processes it `depends_on` are ready.
+    ///
+    /// Process is ready when it connected via IPC and passed its probes, see `probe`.
+    /// Dependencies have to be started before dependent process,
+    /// hence dependency cycles cannot be configured.
+    /// On shutdown dependent processes are stopped before their dependencies.
+    pub fn start_after(
+        &mut self,
+        name: &str,
+        cmd: Command,
+        policy: RestartPolicy,
+        depends_on: &[&str],
+    ) -> anyhow::Result<()> {
+        if self.is_started(name) {
+            return Err(anyhow!("process named `{}` already started", name));
+        }
+        if let Some(dep) = depends_on.iter().find(|dep| !self.is_started(dep)) {
+            return Err(anyhow!(
+                "process `{}` depends on `{}` which is not started, dependencies should be started first",
+                name,
+                dep
+            ));
+        }
+        self.dependencies.insert(
+            name.to_owned(),
+            depends_on.iter().map(|dep| (*dep).to_owned()).collect(),
+        );
+        self.deferred.push_back(Deferred {
+            name: name.to_owned(),
+            cmd,
+            policy,
+            ipc: self.ipc,
+        });
+        Ok(())
+    }
+
+    /// Link process `from` directly to process `to`: orchestrator hands sender of IPC channel
+    /// to `from` and its receiver to `to` during handshake, see `links` module.
+    /// Links should be declared before processes start
+    pub fn link(&mut self, from: &str, to: &str) -> anyhow::Result<()> {
+        if from == to {
+            return Err(anyhow!("process `{}` cannot be linked to itself", from));
+        }
+        if let Some(name) = [from, to].iter().find(|name| self.is_started(name)) {
+            return Err(anyhow!(
+                "process `{}` already started, links should be declared before start",
+                name
+            ));
+        }
+        if self.topology.iter().any(|(f, t)| f == from && t == to) {
+            return Err(anyhow!("`{}` is already linked to `{}`", from, to));
+        }
+        let (tx, rx) = ipc::channel()?;
+        let links = &mut self.links;
+        links
+            .entry(from.to_owned())
+            .or_default()
+            .to
+            .insert(to.to_owned(), tx);
+        links
+            .entry(to.to_owned())
+            .or_default()
+            .from
+            .insert(from.to_owned(), rx);
+        self.topology.push((from.to_owned(), to.to_owned()));
+        Ok(())
+    }
+
+    fn is_started(&self, name: &str) -> bool {
+        self.processes.contains_key(name) || self.deferred.iter().any(|d| d.name == name)
+    }
+
+    fn spawn(
+        &mut self,
+        name: &str,
+        cmd: &mut Command,
+        reconnects: Option<(IpcReceiver<Sender>, IpcReceiver<Receiver>)>,
+    ) -> anyhow::Result<()> {
+        if self.is_started(name) {
             return Err(anyhow::anyhow!("process named `{}` already started", name));
         }
+        let probes = Prepared::new(name, self.probes.remove(name).unwrap_or_default());
+        if probes.ready_message.is_some() && !self.ipc {
+            return Err(anyhow!(
+                "process `{}` should use IPC to send ready message",
+                name
+            ));
+        }
+        if self.liveness.contains_key(name) && !self.ipc {
+            return Err(anyhow!(
+                "process `{}` should use IPC for liveness monitoring",
+                name
+            ));
+        }
 
         let (server, server_name) =
             IpcOneShotServer::new().context("Failed to start IpcOneShotServer")?;
+        let links = match self.links.remove(name) {
+            Some(links) => Some((links::serve(cmd)?, links)),
+            None => None,
+        };
 
-        cmd.kill_on_drop(true).stdout(Stdio::piped());
+        cmd.kill_on_drop(true)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
         if self.ipc {
-            cmd.env("IPC_SERVER", server_name);
+            cmd.env(crate::IPC_SERVER_ENV_VAR, server_name);
         }
         if self.rust_backtrace {
             cmd.env("RUST_BACKTRACE", "1");
@@ -129,13 +386,16 @@ where
 
         let mut child = cmd.spawn()?;
 
-        // Redirect command output to stdout - quick and dirty logging
-        let stdout = child
-            .stdout
-            .take()
-            .ok_or_else(|| anyhow!("child did not provide a handle to stdout"))?;
-        self.loggers.push((self.logger)(stdout, name.to_owned()));
+        // Redirect command output to log - quick and dirty logging
+        let loggers = output_loggers(
+            self.logger,
+            &mut child,
+            name,
+            self.merge_stderr,
+            probes.output,
+        )?;
 
+        let pid = child.id();
         self.processes.insert(
             name.to_owned(),
             Process {
@@ -145,10 +405,54 @@ where
         );
 
         // Spawning Ipc Server to accept incoming channel from child process
-        if self.ipc {
-            self.bridges
-                .push(Box::pin(ipc_handler(server, name.to_owned())));
-        }
+        let state = Arc::new(ProcessState::new(self.liveness.remove(name)));
+        state.connected.store(!self.ipc, Ordering::Relaxed);
+        state.pid.store(pid, Ordering::Relaxed);
+        let bridge = if self.ipc {
+            Some(ipc_handler(
+                server,
+                name.to_owned(),
+                state.clone(),
+                reconnects,
+                probes.ready_message,
+            ))
+        } else {
+            None
+        };
+        let linked = name.to_owned();
+        let timeout = self.handshake_timeout;
+        // Links are handed along with probes checks
+        let handed = async move {
+            match links {
+                Some((server, links)) => links::hand(server, linked, links, timeout).await,
+                None => Ok(()),
+            }
+        };
+        let checks = try_join(try_join_all(probes.checks), handed);
+        let name1 = name.to_owned();
+        self.readiness.push(Box::pin(async move {
+            let bridge = match bridge {
+                Some(bridge) => Some(try_join(bridge, checks).await?.0),
+                None => {
+                    checks.await?;
+                    None
+                }
+            };
+            Ok(Ready {
+                name: name1,
+                bridge,
+            })
+        }));
+
+        self.supervisors.insert(
+            name.to_owned(),
+            Supervisor {
+                loggers,
+                respawn: None,
+                state,
+                stop: self.stop.1.clone(),
+            },
+        );
 
         Ok(())
     }
@@ -156,46 +460,140 @@ where
     /// Connect to processes IPC channels
     /// Resulting ConnectedOrchestrator can be used to further setup handlers
     /// over processes bridges
-    pub async fn connect(self) -> anyhow::Result<ConnectedOrchestrator<Fuse<TryJoinAll<LF>>>> {
-        let Orchestrator {
-            mut processes,
-            bridges,
-            loggers,
-            ..
-        } = self;
-        let processes: Vec<BFR<()>> = processes
-            .drain()
-            .map(|(_k, v)| v)
-            .map(may_exit_process_handler)
-            .collect();
+    ///
+    /// Processes started with `start_after` are started here,
+    /// as soon as their dependencies are ready
+    pub async fn connect(mut self) -> anyhow::Result<ConnectedOrchestrator> {
+        let mut startup = Startup {
+            states: HashMap::new(),
+            processes: FuturesUnordered::new(),
+            readiness: FuturesUnordered::new(),
+            ready: HashSet::new(),
+        };
+        let mut channels = Vec::new();
+        self.supervise(&mut startup);
 
         // Main future executor, had to implement due to customized pipeline
-        // Wait for all bridges to connect to server and pass ipc handles
-        let bridges = try_join_all(bridges).fuse();
-        // Wait for all logs to complete or any of them to fail
-        let mut loggers = Box::pin(try_join_all(loggers).fuse());
-        //let i: u32 = loggers;
-        // Wait for all processes to complete or any of them to fail
-        let mut processes = Box::pin(try_join_all(processes).fuse());
-        pin_mut!(bridges);
-
-        let res = select!(
-            res = bridges => match res {
-                Ok(channels) => { Ok(channels) },
-                Err(err) => { error!("failed to establish connection: {}", err); Err(err.into()) }
-            },
-            res = processes => should_not_complete!("processes", res),
-            res = loggers => should_not_complete!("logs", res),
-        );
+        // Start deferred processes in dependency order,
+        // waiting for all bridges to connect to server and pass ipc handles
+        // and for all the probes to pass
+        let res = loop {
+            if let Err(err) = self.start_ready(&mut startup) {
+                break Err(err);
+            }
+            if startup.readiness.is_empty() {
+                break match self.deferred.front() {
+                    Some(deferred) => Err(anyhow!(
+                        "dependencies of `{}` never got ready",
+                        deferred.name
+                    )),
+                    None => Ok(()),
+                };
+            }
+            // Wait for next process to get ready or any process to fail
+            let res = select!(
+                res = startup.readiness.select_next_some() => res.map(Some),
+                res = startup.processes.next() => match res {
+                    Some(Ok(())) => Ok(None),
+                    Some(Err(err)) => Err(err.context("processes failure")),
+                    None => Err(anyhow!("All the processes exit")),
+                },
+            );
+            match res {
+                Ok(Some(Ready { name, bridge })) => {
+                    debug!(target: "orchestrator", "{} is ready", name);
+                    startup.ready.insert(name);
+                    channels.extend(bridge);
+                }
+                Ok(None) => {}
+                Err(err) => break Err(err),
+            }
+        };
 
         match res {
-            Ok(channels) => Ok(ConnectedOrchestrator::new(channels, processes, loggers)),
+            Ok(()) => {
+                // Connected orchestrator owns the only sender of stop signal,
+                // processes it spawns are supervised with the same stop signal
+                let stop_tx = std::mem::replace(&mut self.stop.0, channel::bounded(0).0);
+                let stop = (stop_tx, self.stop.1.clone());
+                let dependencies = std::mem::take(&mut self.dependencies);
+                Ok(ConnectedOrchestrator::new(
+                    channels,
+                    startup.processes,
+                    startup.states,
+                    dependencies,
+                    self.topology.clone(),
+                    stop,
+                    Box::new(self),
+                ))
+            }
             Err(err) => {
-                error!(target: "orchestrator", "{}", &err);
+                error!(target: "orchestrator", "{:#}", &err);
                 Err(err)
             }
         }
     }
+
+    /// Start deferred processes which dependencies are ready, in order of declaration
+    fn start_ready(&mut self, startup: &mut Startup) -> anyhow::Result<()> {
+        while let Some(pos) = self.deferred.iter().position(|deferred| {
+            self.dependencies[&deferred.name]
+                .iter()
+                .all(|dep| startup.ready.contains(dep))
+        }) {
+            let Deferred {
+                name,
+                cmd,
+                policy,
+                ipc,
+            } = self.deferred.remove(pos).unwrap();
+            info!(target: "orchestrator", "dependencies of {} are ready, starting", name);
+            let global_ipc = self.ipc;
+            self.ipc = ipc;
+            let res = self.start_with_policy(&name, cmd, policy);
+            self.ipc = global_ipc;
+            res?;
+            self.supervise(startup);
+        }
+        Ok(())
+    }
+
+    /// Move started processes and their readiness under supervision
+    fn supervise(&mut self, startup: &mut Startup) {
+        for (name, process) in self.processes.drain() {
+            let supervisor = match self.supervisors.remove(&name) {
+                Some(supervisor) => supervisor,
+                None => continue,
+            };
+            startup.states.insert(name, supervisor.state.clone());
+            startup.processes.push(Box::pin(supervisor.run(process)));
+        }
+        for ready in self.readiness.drain(..) {
+            startup.readiness.push(ready);
+        }
+    }
+}
+
+impl<LF> Spawner for Orchestrator<LF>
+where
+    LF: Future<Output = anyhow::Result<()>> + 'static,
+{
+    fn spawn(
+        &mut self,
+        name: &str,
+        cmd: Command,
+        policy: RestartPolicy,
+    ) -> anyhow::Result<Spawned> {
+        self.start_with_policy(name, cmd, policy)?;
+        let process = self.processes.remove(name).unwrap();
+        let supervisor = self.supervisors.remove(name).unwrap();
+        let ready = self.readiness.pop().unwrap();
+        Ok(Spawned {
+            state: supervisor.state.clone(),
+            process: Box::pin(supervisor.run(process)),
+            ready,
+        })
+    }
 }
 
 impl<LF: TryFuture> Orchestrator<LF> {
@@ -206,61 +604,182 @@ impl<LF: TryFuture> Orchestrator<LF> {
         self
     }
 
+    /// Pass child process stderr to the same log handler as stdout
+    pub fn merge_stderr(mut self, merge: bool) -> Self {
+        self.merge_stderr = merge;
+        self
+    }
+
     /// Start child process with RUST_BACKTRACE=1 env option
     pub fn rust_backtrace(mut self, backtrace: bool) -> Self {
         self.rust_backtrace = backtrace;
         self
     }
+
+    /// Time for linked process to receive its links and for restarted process to connect,
+    /// 30 seconds by default
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+}
+
+async fn ipc_handler(
+    server: IpcOneShotServer<Channel>,
+    name: String,
+    state: Arc<ProcessState>,
+    reconnects: Option<(IpcReceiver<Sender>, IpcReceiver<Receiver>)>,
+    ready_message: Option<Duration>,
+) -> anyhow::Result<Bridge> {
+    let server = tokio::task::spawn_blocking(move || server.accept());
+    let (tx_reconnects, rx_reconnects) = match reconnects {
+        Some((tx, rx)) => (Some(tx), Some(rx)),
+        None => (None, None),
+    };
+    let bridge = match server.await? {
+        Ok((_, channel)) => {
+            state.connected.store(true, Ordering::Relaxed);
+            Bridge {
+                channel,
+                name,
+                tx_reconnects,
+                rx_reconnects,
+            }
+        }
+        Err(err) => {
+            return Err(OrchestratorError::HandshakeFailed {
+                bridge: name,
+                reason: err.to_string(),
+            }
+            .into())
+        }
+    };
+    match ready_message {
+        Some(timeout) => wait_ready_message(bridge, state, timeout).await,
+        None => Ok(bridge),
+    }
 }
 
-async fn ipc_handler(server: IpcOneShotServer<Channel>, name: String) -> anyhow::Result<Bridge> {
-    let name1 = name.clone();
-    let server = tokio::task::spawn_blocking(move || {
-        server
-            .accept()
-            .unwrap_or_else(|err| todo!("failed to establish connection from {}: {}", name1, err))
+/// Wait for process to send ready message before any data message.
+/// Control messages sent before are recorded in process `state`
+async fn wait_ready_message(
+    bridge: Bridge,
+    state: Arc<ProcessState>,
+    timeout: Duration,
+) -> anyhow::Result<Bridge> {
+    let name = bridge.name.clone();
+    // Receiving thread gives up on timeout, so that bridge is dropped with its channel
+    let recv = tokio::task::spawn_blocking(move || {
+        let ready = match bridge.channel.rx() {
+            Some(rx) => recv_ready(rx, &state, timeout),
+            None => Err(anyhow!("receiver was taken")),
+        };
+        (bridge, ready)
     });
-    let name = name.clone();
-    server
-        .map(|res| match res {
-            Ok((_, channel)) => Ok(Bridge { channel, name }),
-            Err(err) => Err(err.into()),
-        })
-        .await
+    let reason = match recv.await? {
+        (bridge, Ok(())) => return Ok(bridge),
+        (_, Err(err)) => err.to_string(),
+    };
+    Err(OrchestratorError::NotReady {
+        process: name,
+        reason,
+    }
+    .into())
 }
 
-#[allow(dead_code)]
-fn never_exit_process_handler(p: Process) -> BFR<()> {
-    let Process { child, name } = p;
-    let name1 = name.clone();
-    Box::pin(
-        child
-            .inspect(move |status| warn!(target: &name1, "exiting {:?}", status))
-            .map(move |status| match status {
-                Ok(n) => Err(anyhow!(
-                    "process `{}` finish with {}, closing pipeline",
-                    name,
-                    n
-                )),
-                Err(err) => Err(err.into()),
-            }),
-    )
+/// Receive until ready message, subscriptions and headers request are recorded in `state`
+fn recv_ready(rx: &Receiver, state: &ProcessState, timeout: Duration) -> anyhow::Result<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let msg = match try_recv_timeout(rx, left) {
+            Ok(Some(msg)) => msg.split_headers().0,
+            Ok(None) => {
+                return Err(anyhow!(
+                    "ready message probe did not pass within {:?}",
+                    timeout
+                ))
+            }
+            Err(err) => return Err(anyhow!("failed to receive ready message: {}", err)),
+        };
+        state.seen(&msg);
+        match msg.topic.as_str() {
+            READY_TOPIC => return Ok(()),
+            SUBSCRIBE_TOPIC | UNSUBSCRIBE_TOPIC => state.subscriptions.lock().unwrap().push(msg),
+            _ if msg.is_control() => debug!("control message {} before ready message", msg.topic),
+            _ => {
+                return Err(anyhow!(
+                    "received message to topic `{}` before ready message",
+                    msg.topic
+                ))
+            }
+        }
+    }
 }
 
-fn may_exit_process_handler(p: Process) -> BFR<()> {
-    let Process { child, name } = p;
-    let name1 = name.clone();
-    Box::pin(
-        child
-            .inspect(move |status| warn!(target: &name1, "exiting {:?}", status))
-            .map(move |status| match status {
-                Ok(n) if n.success() => Ok(()),
-                Ok(n) => Err(anyhow!(
-                    "process `{}` finish with {}, closing pipeline",
-                    name,
-                    n
-                )),
-                Err(err) => Err(err.into()),
-            }),
-    )
+/// Blocking receive of message within `timeout`, None when no message was received
+fn try_recv_timeout(rx: &Receiver, timeout: Duration) -> anyhow::Result<Option<Message>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match rx.try_recv() {
+            Ok(msg) => return Ok(Some(msg)),
+            Err(err) => match *err {
+                bincode::ErrorKind::Io(ref err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                _ => return Err(err.into()),
+            },
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        std::thread::sleep(READY_POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Headers;
+
+    fn recv_ready_after(messages: Vec<Message>) -> (anyhow::Result<()>, ProcessState) {
+        let (tx, rx) = ipc::channel().unwrap();
+        for msg in messages {
+            tx.send(msg).unwrap();
+        }
+        let state = ProcessState::default();
+        let ready = recv_ready(&rx, &state, Duration::from_millis(100));
+        (ready, state)
+    }
+
+    #[test]
+    fn control_messages_before_ready_are_recorded() {
+        let (ready, state) = recv_ready_after(vec![
+            Message::headers_on(),
+            Message::subscribe("a.*").with_headers(&Headers::default()),
+            Message::heartbeat(),
+            Message::ready(),
+        ]);
+        assert!(ready.is_ok());
+        assert!(state.headers.load(Ordering::Relaxed));
+        let subscriptions = state.take_subscriptions();
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(subscriptions[0].data, b"a.*");
+    }
+
+    #[test]
+    fn data_message_before_ready_fails() {
+        let data = Message {
+            topic: "a".to_owned(),
+            data: Vec::new(),
+        };
+        let (ready, state) = recv_ready_after(vec![Message::subscribe("a"), data]);
+        let err = ready.unwrap_err().to_string();
+        assert!(err.contains("topic `a` before ready message"), "{}", err);
+        assert_eq!(state.take_subscriptions().len(), 1);
+    }
+
+    #[test]
+    fn no_ready_message_times_out() {
+        let (ready, _) = recv_ready_after(vec![Message::heartbeat()]);
+        assert!(ready.unwrap_err().to_string().contains("did not pass"));
+    }
 }
@@ -0,0 +1,184 @@
+//! Readiness probes of orchestrated processes
+//!
+//! Process is ready when it connected via IPC (if IPC is used) and all its probes passed:
+//! - `output_line` - process printed line matching regex to stdout
+//! - `tcp_port` - address accepts TCP connections
+//! - `file` - file appeared
+//! - `ready_message` - process sent `Message::ready()` before any data message,
+//!   see `notify_ready`. Control messages sent before it, like subscriptions, are kept
+//!
+//! `Orchestrator::connect()` resolves once every probe passed,
+//! or fails with error naming the process whose probe did not pass within its timeout.
+//!
+//! Output line probe is matched as lines are read by log handler,
+//! hence custom log handler should keep reading process output.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use tokio::process::Command;
+//! use ipc_orchestrator::{orchestrator, Probe};
+//!
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//!     let mut orchestrator = orchestrator().ipc(false);
+//!     orchestrator.probe("server", Probe::output_line("^listening").unwrap()).unwrap();
+//!     orchestrator.probe("server", Probe::file("/").timeout(Duration::from_secs(1))).unwrap();
+//!     let mut cmd = Command::new("sh");
+//!     cmd.arg("-c").arg("echo starting; echo listening; sleep 100");
+//!     orchestrator.start("server", &mut cmd).unwrap();
+//!     let orchestra = orchestrator.connect().await.unwrap();
+//!     # orchestra.shutdown(Duration::from_secs(1)).await.unwrap();
+//! # });
+//! ```
+
+use crate::error::OrchestratorError;
+use anyhow::{anyhow, Context};
+use futures::channel::oneshot;
+use futures::future::Future;
+use regex::Regex;
+use std::fmt;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// How often TCP port and file probes are checked
+const PROBE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Readiness check of a process with its timeout
+#[derive(Clone, Debug)]
+pub struct Probe {
+    check: Check,
+    timeout: Duration,
+}
+
+#[derive(Clone, Debug)]
+enum Check {
+    OutputLine(Regex),
+    TcpPort(String),
+    File(PathBuf),
+    ReadyMessage,
+}
+
+impl Probe {
+    /// Default time given to probe to pass
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+    fn new(check: Check) -> Self {
+        Self {
+            check,
+            timeout: Self::DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Process printed line matching `pattern` to stdout
+    pub fn output_line(pattern: &str) -> anyhow::Result<Self> {
+        let regex = Regex::new(pattern)
+            .with_context(|| format!("invalid output line probe `{}`", pattern))?;
+        Ok(Self::new(Check::OutputLine(regex)))
+    }
+
+    /// Address `addr`, e.g. "127.0.0.1:8080", accepts TCP connections
+    pub fn tcp_port(addr: &str) -> Self {
+        Self::new(Check::TcpPort(addr.to_owned()))
+    }
+
+    /// File at `path` exists
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        Self::new(Check::File(path.into()))
+    }
+
+    /// Process sent ready message via IPC before any data message
+    pub fn ready_message() -> Self {
+        Self::new(Check::ReadyMessage)
+    }
+
+    /// Set time given to probe to pass after process started
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.check {
+            Check::OutputLine(regex) => write!(f, "output line `{}`", regex),
+            Check::TcpPort(addr) => write!(f, "tcp port {}", addr),
+            Check::File(path) => write!(f, "file {}", path.display()),
+            Check::ReadyMessage => write!(f, "ready message"),
+        }
+    }
+}
+
+type Checks = Vec<Pin<Box<dyn Future<Output = anyhow::Result<()>>>>>;
+
+/// Probes of a process prepared for its start
+#[derive(Default)]
+pub(crate) struct Prepared {
+    /// Timeout of ready message probe
+    pub ready_message: Option<Duration>,
+    /// Output line probes to be matched by OutputLines
+    pub output: Vec<(Regex, oneshot::Sender<()>)>,
+    /// Futures which complete when probes pass
+    pub checks: Checks,
+}
+
+impl Prepared {
+    pub fn new(name: &str, probes: Vec<Probe>) -> Self {
+        let mut prepared = Prepared::default();
+        for probe in probes {
+            let desc = probe.to_string();
+            let name = name.to_owned();
+            let timeout = probe.timeout;
+            let check: Pin<Box<dyn Future<Output = anyhow::Result<()>>>> = match probe.check {
+                Check::ReadyMessage => {
+                    prepared.ready_message = prepared.ready_message.max(Some(timeout));
+                    continue;
+                }
+                Check::OutputLine(regex) => {
+                    let (tx, rx) = oneshot::channel();
+                    prepared.output.push((regex, tx));
+                    Box::pin(async move {
+                        rx.await
+                            .map_err(|_| anyhow!("output closed before matching line"))
+                    })
+                }
+                Check::TcpPort(addr) => Box::pin(tcp_port(addr)),
+                Check::File(path) => Box::pin(async move {
+                    while !path.exists() {
+                        tokio::time::delay_for(PROBE_INTERVAL).await;
+                    }
+                    Ok(())
+                }),
+            };
+            prepared.checks.push(Box::pin(async move {
+                let reason = match tokio::time::timeout(timeout, check).await {
+                    Ok(Ok(())) => return Ok(()),
+                    Ok(Err(err)) => format!("{} probe failed: {}", desc, err),
+                    Err(_) => format!("{} probe did not pass within {:?}", desc, timeout),
+                };
+                Err(OrchestratorError::NotReady {
+                    process: name,
+                    reason,
+                }
+                .into())
+            }));
+        }
+        prepared
+    }
+}
+
+async fn tcp_port(addr: String) -> anyhow::Result<()> {
+    loop {
+        let addr = addr.clone();
+        let connected = tokio::task::spawn_blocking(move || {
+            std::net::TcpStream::connect(addr.as_str()).is_ok()
+        })
+        .await?;
+        if connected {
+            return Ok(());
+        }
+        tokio::time::delay_for(PROBE_INTERVAL).await;
+    }
+}
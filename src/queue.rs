@@ -0,0 +1,304 @@
+//! Bounded per-subscriber queues of router
+//!
+//! With `ConnectedOrchestrator::pipe_routes_via_queues` router puts messages into bounded queue
+//! of every recipient bridge, each queue is sent to its process by a separate thread.
+//! Slow process can neither stall the router nor make it grow on memory,
+//! when its queue is full `Overflow` policy applies:
+//! - `Block` - router waits for queue to have space, as `pipe_routes` does
+//! - `DropNewest` - message is dropped
+//! - `DropOldest` - the oldest message in queue is dropped to make space
+//! - `Disconnect` - slow process is disconnected, its channel is closed
+//!   and further messages to it are dropped until process is restarted
+//!   according to its restart policy, then its queue is reset
+//!
+//! Queue depths and dropped messages are reported by `ConnectedOrchestrator::queues()`.
+
+use crate::error::OrchestratorError;
+use crate::message::{Headers, Message};
+use crate::restart::BridgeTx;
+use crate::shutdown::Stop;
+use crossbeam::channel::{self, TrySendError};
+use log::{info, warn};
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// What router does when queue of recipient is full
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Overflow {
+    /// Wait until queue has space
+    #[default]
+    Block,
+    /// Drop message which does not fit into queue
+    DropNewest,
+    /// Drop the oldest message in queue
+    DropOldest,
+    /// Close channel of recipient and drop all further messages to it until it is restarted
+    Disconnect,
+}
+
+/// State of recipient queue
+#[derive(Clone, Debug)]
+pub struct QueueStats {
+    /// Messages waiting in queue
+    pub depth: usize,
+    pub capacity: usize,
+    /// Messages dropped on overflow or after recipient was disconnected
+    pub dropped: u64,
+    /// Recipient was disconnected on overflow and was not restarted yet
+    pub disconnected: bool,
+}
+
+/// Router end of queue, queue closes once router drops it
+pub(crate) type QueueTx = channel::Sender<(Message, Headers)>;
+
+/// Queue shared by router, sending thread and orchestrator
+#[derive(Debug)]
+pub(crate) struct Queue {
+    name: String,
+    capacity: usize,
+    overflow: Overflow,
+    rx: channel::Receiver<(Message, Headers)>,
+    dropped: AtomicU64,
+    disconnected: AtomicBool,
+}
+
+impl Queue {
+    pub fn new(name: &str, capacity: usize, overflow: Overflow) -> (Arc<Self>, QueueTx) {
+        let (tx, rx) = channel::bounded(capacity);
+        let queue = Arc::new(Self {
+            name: name.to_owned(),
+            capacity,
+            overflow,
+            rx,
+            dropped: AtomicU64::new(0),
+            disconnected: AtomicBool::new(false),
+        });
+        (queue, tx)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        QueueStats {
+            depth: self.rx.len(),
+            capacity: self.capacity,
+            dropped: self.dropped.load(Ordering::Relaxed),
+            disconnected: self.disconnected.load(Ordering::Relaxed),
+        }
+    }
+
+    fn drop_message(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Put message into queue applying overflow policy,
+    /// blocking queue is waited for until orchestrator stops
+    pub fn push(&self, tx: &QueueTx, msg: Message, headers: Headers, stop: &Stop) {
+        let mut msg = (msg, headers);
+        if self.disconnected.load(Ordering::Relaxed) {
+            self.drop_message();
+            return;
+        }
+        if self.overflow == Overflow::Block {
+            // Queue keeps its receiver, hence sending fails only on stop
+            crossbeam::select! {
+                send(tx, msg) -> _ => {},
+                recv(stop.receiver()) -> _ => {},
+            }
+            return;
+        }
+        loop {
+            msg = match tx.try_send(msg) {
+                Ok(()) | Err(TrySendError::Disconnected(_)) => return,
+                Err(TrySendError::Full(msg)) => msg,
+            };
+            match self.overflow {
+                Overflow::DropOldest => {
+                    if self.rx.try_recv().is_ok() {
+                        self.drop_message();
+                    }
+                }
+                Overflow::Disconnect => {
+                    warn!("disconnecting `{}`, its queue is full", self.name);
+                    self.disconnected.store(true, Ordering::Relaxed);
+                    self.drop_message();
+                    return;
+                }
+                _ => {
+                    self.drop_message();
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Send queued messages to process until router stops or orchestrator stops,
+    /// disconnected process is sent to again once restarted
+    pub fn forward(&self, mut tx: BridgeTx, stop: &Stop) -> anyhow::Result<()> {
+        loop {
+            let msg = crossbeam::select! {
+                recv(self.rx) -> msg => match msg {
+                    Ok(msg) => msg,
+                    Err(_) => return Ok(()),
+                },
+                recv(stop.receiver()) -> _ => return Ok(()),
+            };
+            if self.disconnected.load(Ordering::Relaxed) {
+                self.drop_message();
+                while self.rx.try_recv().is_ok() {
+                    self.drop_message();
+                }
+                // Closed channel makes process exit, restarted process is sent to again
+                tx = match tx.reconnect(stop) {
+                    Some(tx) => tx,
+                    None => return Ok(()),
+                };
+                info!("reconnected `{}`, its queue is reset", self.name);
+                self.disconnected.store(false, Ordering::Relaxed);
+                continue;
+            }
+            let (msg, headers) = msg;
+            let topic = msg.topic.clone();
+            match tx.send(msg, Some(&headers)) {
+                Ok(()) => {}
+                Err(_) if stop.is_stopping() || tx.is_stopped() => return Ok(()),
+                Err(err) => {
+                    return Err(OrchestratorError::send(&self.name, Some(&topic), err).into())
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::Channel;
+    use crate::stats::Counter;
+
+    fn message(topic: &str) -> Message {
+        Message {
+            topic: topic.to_owned(),
+            data: vec![],
+        }
+    }
+
+    fn push(queue: &Queue, tx: &QueueTx, topics: &[&str]) {
+        let (_stop_tx, stop) = Stop::new();
+        for topic in topics {
+            queue.push(tx, message(topic), Headers::default(), &stop);
+        }
+    }
+
+    fn queued(queue: &Queue) -> Vec<String> {
+        queue.rx.try_iter().map(|(msg, _)| msg.topic).collect()
+    }
+
+    #[test]
+    fn drop_newest() {
+        let (queue, tx) = Queue::new("slow", 2, Overflow::DropNewest);
+        push(&queue, &tx, &["1", "2", "3"]);
+        assert_eq!(queue.stats().depth, 2);
+        assert_eq!(queue.stats().dropped, 1);
+        assert_eq!(queued(&queue), vec!["1", "2"]);
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let (queue, tx) = Queue::new("slow", 2, Overflow::DropOldest);
+        push(&queue, &tx, &["1", "2", "3", "4"]);
+        assert_eq!(queue.stats().dropped, 2);
+        assert_eq!(queued(&queue), vec!["3", "4"]);
+    }
+
+    #[test]
+    fn disconnect() {
+        let (queue, tx) = Queue::new("slow", 2, Overflow::Disconnect);
+        push(&queue, &tx, &["1", "2", "3", "4"]);
+        let stats = queue.stats();
+        assert!(stats.disconnected);
+        assert_eq!(stats.dropped, 2);
+
+        // Queued messages are dropped instead of being sent, closing channel of process
+        let (bridge, process) = Channel::duplex().unwrap();
+        let (bridge, _) = bridge.split().unwrap();
+        let (_, process) = process.split().unwrap();
+        let bridge = BridgeTx::new(
+            "slow".to_owned(),
+            bridge,
+            None,
+            None,
+            Counter::default().into(),
+        );
+        let (_stop_tx, stop) = Stop::new();
+        queue.forward(bridge, &stop).unwrap();
+        assert_eq!(queue.stats().dropped, 4);
+        assert!(process.recv().is_err());
+    }
+
+    #[test]
+    fn forward_until_router_drops_queue() {
+        let (queue, tx) = Queue::new("slow", 2, Overflow::Block);
+        push(&queue, &tx, &["1", "2"]);
+        drop(tx);
+        let (bridge, process) = Channel::duplex().unwrap();
+        let (bridge, _) = bridge.split().unwrap();
+        let (_, process) = process.split().unwrap();
+        let bridge = BridgeTx::new(
+            "slow".to_owned(),
+            bridge,
+            None,
+            None,
+            Counter::default().into(),
+        );
+        let (_stop_tx, stop) = Stop::new();
+        queue.forward(bridge, &stop).unwrap();
+        assert_eq!(process.recv().unwrap().topic, "1");
+        assert_eq!(process.recv().unwrap().topic, "2");
+        assert!(process.recv().is_err());
+        assert_eq!(queue.stats().dropped, 0);
+    }
+
+    #[test]
+    fn disconnected_process_is_sent_to_once_restarted() {
+        let (queue, tx) = Queue::new("slow", 2, Overflow::Disconnect);
+        push(&queue, &tx, &["1", "2", "3"]);
+        assert!(queue.stats().disconnected);
+
+        let (bridge, process) = Channel::duplex().unwrap();
+        let (bridge, _) = bridge.split().unwrap();
+        let (_, process) = process.split().unwrap();
+        let (reconnect, reconnects) = ipc_channel::ipc::channel().unwrap();
+        let bridge = BridgeTx::new(
+            "slow".to_owned(),
+            bridge,
+            Some(reconnects),
+            None,
+            Counter::default().into(),
+        );
+        let (_stop_tx, stop) = Stop::new();
+        let forwarding = {
+            let queue = queue.clone();
+            std::thread::spawn(move || queue.forward(bridge, &stop))
+        };
+        assert!(process.recv().is_err());
+
+        let (bridge, restarted) = Channel::duplex().unwrap();
+        let (bridge, _) = bridge.split().unwrap();
+        let (_, restarted) = restarted.split().unwrap();
+        reconnect.send(bridge).unwrap();
+        while queue.stats().disconnected {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        push(&queue, &tx, &["4"]);
+        assert_eq!(restarted.recv().unwrap().topic, "4");
+        assert_eq!(queue.stats().dropped, 3);
+
+        drop(tx);
+        forwarding.join().unwrap().unwrap();
+    }
+}
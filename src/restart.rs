@@ -0,0 +1,809 @@
+//! Restart policies for orchestrated processes
+//!
+//! Process started with `RestartPolicy` other than `never()` is supervised:
+//! when it exits orchestrator will start it again after backoff delay.
+//! Restarted process gets fresh `IpcOneShotServer` handshake and fresh output loggers,
+//! its new IPC channel is delivered to bridge's current owners (pipes and routers)
+//! via reconnect feeds, so routing continues without rebuilding orchestrator.
+//! Restarted process which does not connect within `Orchestrator::handshake_timeout` is killed,
+//! which counts as another failed restart.
+//!
+//! Messages sent to a process while it is restarting are dropped.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use ipc_orchestrator::RestartPolicy;
+//!
+//! let policy = RestartPolicy::on_failure()
+//!     .backoff(Duration::from_millis(100), Duration::from_secs(10))
+//!     .max_restarts(5, Duration::from_secs(60));
+//! ```
+
+use crate::client::PeerLinks;
+use crate::error::OrchestratorError;
+use crate::links;
+use crate::liveness::{Activity, Liveness};
+use crate::logger::{output_loggers, OutputLines};
+use crate::message::{Headers, Message, HEADERS_ON_TOPIC};
+use crate::shutdown::{signal, Stop};
+use crate::stats::Counter;
+use crate::{Channel, Process, Receiver, Sender};
+use anyhow::Context;
+use futures::future::{self, Future, FutureExt};
+use futures::stream::{FuturesUnordered, StreamExt};
+use futures::{pin_mut, select};
+use ipc_channel::ipc::{self, IpcOneShotServer, IpcReceiver, IpcSender};
+use log::{debug, error, info, trace, warn};
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::process::Command;
+
+/// How often restart backoff checks whether process or orchestrator was stopped
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// When process should be restarted after exit
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Restart {
+    /// Never restart, process exit is reported to orchestrator
+    Never,
+    /// Restart only if process exit with non-zero status or was killed
+    OnFailure,
+    /// Restart on any exit
+    Always,
+}
+
+/// Per process restart policy with exponential backoff
+///
+/// Backoff starts from `initial` delay and doubles with every restart
+/// within the window, up to `max` delay.
+/// Once process was restarted `max_restarts` times within `window`
+/// orchestrator gives up and reports process failure.
+#[derive(Clone, Debug)]
+pub struct RestartPolicy {
+    restart: Restart,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    max_restarts: usize,
+    window: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self::never()
+    }
+}
+
+impl RestartPolicy {
+    fn new(restart: Restart) -> Self {
+        Self {
+            restart,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            max_restarts: 5,
+            window: Duration::from_secs(60),
+        }
+    }
+
+    /// Never restart process
+    pub fn never() -> Self {
+        Self::new(Restart::Never)
+    }
+
+    /// Restart process when it exits with failure
+    pub fn on_failure() -> Self {
+        Self::new(Restart::OnFailure)
+    }
+
+    /// Restart process whenever it exits
+    pub fn always() -> Self {
+        Self::new(Restart::Always)
+    }
+
+    /// Set exponential backoff from `initial` delay up to `max` delay
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Give up after `max_restarts` restarts happened within `window`
+    pub fn max_restarts(mut self, max_restarts: usize, window: Duration) -> Self {
+        self.max_restarts = max_restarts;
+        self.window = window;
+        self
+    }
+
+    pub fn restart(&self) -> Restart {
+        self.restart
+    }
+
+    fn should_restart(&self, status: &std::io::Result<ExitStatus>) -> bool {
+        match (self.restart, status) {
+            (Restart::Never, _) => false,
+            (Restart::Always, _) => true,
+            (Restart::OnFailure, Ok(status)) => !status.success(),
+            (Restart::OnFailure, Err(_)) => true,
+        }
+    }
+
+    /// Forget `restarts` which happened more than `window` before `now`,
+    /// returns number of recent restarts
+    fn prune(&self, restarts: &mut VecDeque<Instant>, now: Instant) -> usize {
+        while restarts
+            .front()
+            .is_some_and(|t| now.saturating_duration_since(*t) > self.window)
+        {
+            restarts.pop_front();
+        }
+        restarts.len()
+    }
+
+    /// Backoff delay before next restart given number of recent restarts
+    fn delay(&self, recent: usize) -> Duration {
+        let factor = 1u32.checked_shl(recent as u32).unwrap_or(u32::MAX);
+        self.initial_backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+/// Receiver, reconnects feed, process state and traffic counter of `BridgeRx`
+pub(crate) type RxParts = (
+    Receiver,
+    Option<IpcReceiver<Receiver>>,
+    Option<Arc<ProcessState>>,
+    Arc<Counter>,
+);
+
+/// Receiving end of a bridge, which follows process across restarts
+#[derive(Debug)]
+pub(crate) struct BridgeRx {
+    pub name: String,
+    rx: Receiver,
+    reconnects: Option<IpcReceiver<Receiver>>,
+    state: Option<Arc<ProcessState>>,
+    traffic: Arc<Counter>,
+    stamp: Stamp,
+}
+
+impl BridgeRx {
+    pub fn new(
+        name: String,
+        rx: Receiver,
+        reconnects: Option<IpcReceiver<Receiver>>,
+        state: Option<Arc<ProcessState>>,
+        traffic: Arc<Counter>,
+    ) -> Self {
+        if let Some(state) = state.as_ref() {
+            state.arm();
+        }
+        Self {
+            stamp: Stamp::new(&name),
+            name,
+            rx,
+            reconnects,
+            state,
+            traffic,
+        }
+    }
+
+    /// Blocking receive of message with its stamped headers,
+    /// when channel closes waits for restarted process channel.
+    /// Control messages are skipped
+    pub fn recv(&mut self) -> Result<(Message, Headers), OrchestratorError> {
+        loop {
+            let msg = self.rx.recv().map(Message::split_headers);
+            if let (Ok((msg, _)), Some(state)) = (msg.as_ref(), self.state.as_ref()) {
+                state.seen(msg);
+            }
+            match msg {
+                // Heartbeats and readiness of restarted process are not routed
+                Ok((msg, _)) if msg.is_control() && !msg.is_for_router() => {
+                    trace!("control message from {}", self.name)
+                }
+                Ok((msg, headers)) => {
+                    self.traffic.record(msg.data.len());
+                    return Ok((msg, self.stamp.stamp(headers)));
+                }
+                Err(err) => match self.reconnects.as_ref().map(|r| r.recv()) {
+                    Some(Ok(rx)) => {
+                        info!("receiving from restarted {}", self.name);
+                        self.rx = rx;
+                    }
+                    _ => return Err(OrchestratorError::recv(&self.name, err)),
+                },
+            }
+        }
+    }
+
+    /// Process was stopped with `ConnectedOrchestrator::stop`, its channel closes for good
+    pub fn is_stopped(&self) -> bool {
+        is_stopped(&self.state)
+    }
+
+    /// Split into current receiver, feed of receivers from restarted processes,
+    /// process state and traffic counter to record received messages
+    pub fn into_parts(self) -> RxParts {
+        (self.rx, self.reconnects, self.state, self.traffic)
+    }
+}
+
+/// Sending end of a bridge, which follows process across restarts
+#[derive(Debug)]
+pub(crate) struct BridgeTx {
+    pub name: String,
+    tx: Sender,
+    reconnects: Option<IpcReceiver<Sender>>,
+    state: Option<Arc<ProcessState>>,
+    traffic: Arc<Counter>,
+}
+
+impl BridgeTx {
+    pub fn new(
+        name: String,
+        tx: Sender,
+        reconnects: Option<IpcReceiver<Sender>>,
+        state: Option<Arc<ProcessState>>,
+        traffic: Arc<Counter>,
+    ) -> Self {
+        Self {
+            name,
+            tx,
+            reconnects,
+            state,
+            traffic,
+        }
+    }
+
+    /// Process was stopped with `ConnectedOrchestrator::stop`, its channel closes for good
+    pub fn is_stopped(&self) -> bool {
+        is_stopped(&self.state)
+    }
+
+    /// Send message to the process, in envelope with `headers` if process asked for headers.
+    /// If process is being restarted message will be dropped
+    pub fn send(
+        &mut self,
+        msg: Message,
+        headers: Option<&Headers>,
+    ) -> Result<(), ipc_channel::Error> {
+        let size = msg.data.len();
+        let headers = headers.filter(|_| {
+            self.state
+                .as_ref()
+                .is_some_and(|state| state.headers.load(Ordering::Relaxed))
+        });
+        let msg = match headers {
+            Some(headers) => msg.with_headers(headers),
+            None => msg,
+        };
+        let err = match self.tx.send(msg) {
+            Ok(()) => {
+                self.traffic.record(size);
+                return Ok(());
+            }
+            Err(err) => err,
+        };
+        self.traffic.error();
+        match self.reconnects.as_ref() {
+            Some(reconnects) => {
+                warn!("dropped message to restarting {}: {}", self.name, err);
+                // Process might have been restarted several times already, take the latest
+                while let Ok(tx) = reconnects.try_recv() {
+                    info!("sending to restarted {}", self.name);
+                    self.tx = tx;
+                }
+                Ok(())
+            }
+            None => Err(err),
+        }
+    }
+
+    /// Close channel of process and wait for process to be restarted,
+    /// returns bridge to restarted process or None when process is not restarted
+    pub fn reconnect(self, stop: &Stop) -> Option<BridgeTx> {
+        let BridgeTx {
+            name,
+            tx,
+            reconnects,
+            state,
+            traffic,
+        } = self;
+        drop(tx);
+        let reconnects = reconnects?;
+        let tx = loop {
+            match reconnects.try_recv() {
+                Ok(tx) => break tx,
+                Err(err) => match *err {
+                    bincode::ErrorKind::Io(ref err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                    _ => return None,
+                },
+            }
+            if stop.is_stopping() || is_stopped(&state) {
+                return None;
+            }
+            std::thread::sleep(STOP_POLL_INTERVAL);
+        };
+        info!("sending to restarted {}", name);
+        let mut bridge = BridgeTx::new(name, tx, Some(reconnects), state, traffic);
+        // Process might have been restarted several times already, take the latest
+        if let Some(reconnects) = bridge.reconnects.as_ref() {
+            while let Ok(tx) = reconnects.try_recv() {
+                bridge.tx = tx;
+            }
+        }
+        Some(bridge)
+    }
+}
+
+fn is_stopped(state: &Option<Arc<ProcessState>>) -> bool {
+    state
+        .as_ref()
+        .is_some_and(|state| state.stopped.load(Ordering::Relaxed))
+}
+
+/// Stamps headers of messages received from bridge
+#[derive(Debug)]
+pub(crate) struct Stamp {
+    source: String,
+    sequence: u64,
+}
+
+impl Stamp {
+    pub fn new(source: &str) -> Self {
+        Self {
+            source: source.to_owned(),
+            sequence: 0,
+        }
+    }
+
+    /// Headers of received message: source bridge, sequence number and timestamp
+    /// are stamped unless process has set them in `headers` sent with message
+    pub fn stamp(&mut self, headers: Option<Headers>) -> Headers {
+        self.sequence += 1;
+        let mut headers = headers.unwrap_or_default();
+        headers.source = Some(self.source.clone());
+        headers.sequence = headers.sequence.or(Some(self.sequence));
+        headers.timestamp = headers.timestamp.or_else(|| Some(SystemTime::now()));
+        headers
+    }
+}
+
+/// Delivers channels of restarted process to the bridge ends
+#[derive(Clone)]
+pub(crate) struct Reconnector {
+    tx: IpcSender<Sender>,
+    rx: IpcSender<Receiver>,
+}
+
+impl Reconnector {
+    /// Create reconnector with its feeds of senders and receivers
+    pub fn new() -> anyhow::Result<(Self, IpcReceiver<Sender>, IpcReceiver<Receiver>)> {
+        let (tx, tx_feed) = ipc::channel()?;
+        let (rx, rx_feed) = ipc::channel()?;
+        Ok((Self { tx, rx }, tx_feed, rx_feed))
+    }
+
+    fn reconnect(&self, channel: Channel) -> anyhow::Result<()> {
+        let (tx, rx) = channel.split()?;
+        self.tx.send(tx)?;
+        self.rx.send(rx)?;
+        Ok(())
+    }
+}
+
+/// Everything required to start process again
+pub(crate) struct Respawn<LF> {
+    pub cmd: Command,
+    pub policy: RestartPolicy,
+    pub logger: fn(OutputLines, String) -> LF,
+    pub merge_stderr: bool,
+    /// Time for restarted process to connect and receive its links
+    pub handshake_timeout: Duration,
+    pub reconnector: Option<Reconnector>,
+    /// Senders of peer links handed to process, see `Orchestrator::link`
+    pub links: Option<HashMap<String, Sender>>,
+}
+
+impl<LF> Respawn<LF>
+where
+    LF: Future<Output = anyhow::Result<()>>,
+{
+    fn spawn(
+        &mut self,
+        name: &str,
+        state: &Arc<ProcessState>,
+        stop: &Stop,
+    ) -> anyhow::Result<(tokio::process::Child, Vec<LF>)> {
+        let server = match self.reconnector {
+            Some(_) => {
+                let (server, server_name) = IpcOneShotServer::<Channel>::new()
+                    .context("Failed to start IpcOneShotServer")?;
+                self.cmd.env(crate::IPC_SERVER_ENV_VAR, &server_name);
+                Some((server, server_name))
+            }
+            None => None,
+        };
+        let links = match self.links.as_ref() {
+            Some(to) => {
+                let links = PeerLinks {
+                    to: to.clone(),
+                    from: HashMap::new(),
+                };
+                Some((links::serve(&mut self.cmd)?, links))
+            }
+            None => None,
+        };
+
+        debug!(target: "orchestrator", "Restarting {} {:?}", name, self.cmd);
+        let mut child = self.cmd.spawn()?;
+        let loggers = output_loggers(self.logger, &mut child, name, self.merge_stderr, Vec::new())?;
+
+        if let (Some((server, server_name)), Some(reconnector)) = (server, self.reconnector.clone())
+        {
+            let (name, state, stop) = (name.to_owned(), state.clone(), stop.clone());
+            let timeout = self.handshake_timeout;
+            let pid = child.id();
+            // Child might exit before connecting, hence handshake is not awaited
+            tokio::task::spawn_blocking(move || {
+                match links::accept_within(server, server_name, timeout, stop.receiver(), || {
+                    Ok(Channel::duplex()?.0)
+                }) {
+                    Ok(channel) => reconnector.reconnect(channel),
+                    Err(err) => {
+                        // Restart failed, killed process is handled by restart policy
+                        if !stop.is_stopping() && state.pid.load(Ordering::Relaxed) == pid {
+                            signal(pid, libc::SIGKILL);
+                        }
+                        Err(err)
+                    }
+                }
+                .unwrap_or_else(|err| error!("failed to reconnect {}: {}", name, err))
+            });
+        }
+        if let Some((server, links)) = links {
+            let name = name.to_owned();
+            let timeout = self.handshake_timeout;
+            tokio::task::spawn_blocking(move || {
+                links::accept(server, links, timeout)
+                    .unwrap_or_else(|err| error!("failed to hand links to {}: {}", name, err))
+            });
+        }
+
+        Ok((child, loggers))
+    }
+}
+
+/// States of processes and in-process participants by name, read by metrics endpoint
+pub(crate) type LiveStates = Arc<RwLock<HashMap<String, Arc<ProcessState>>>>;
+
+/// State of supervised process shared with orchestrator
+#[derive(Debug, Default)]
+pub(crate) struct ProcessState {
+    pub restarts: AtomicUsize,
+    /// Process connected to orchestrator, processes are not restarted before connecting
+    pub connected: AtomicBool,
+    /// Pid of running process, 0 when process is not running
+    pub pid: AtomicU32,
+    /// Last exit status
+    pub exit: Mutex<Option<ExitStatus>>,
+    /// Process asked for messages sent to it in envelopes with headers
+    pub headers: AtomicBool,
+    /// Process was stopped on its own, it is not restarted
+    pub stopped: AtomicBool,
+    /// Liveness conditions with messages observed from process
+    pub liveness: Option<(Liveness, Mutex<Activity>)>,
+    /// In-process participant, it runs until it is stopped
+    pub in_process: bool,
+    /// Subscriptions sent by process before its ready message, applied once it joins router
+    pub subscriptions: Mutex<Vec<Message>>,
+}
+
+impl ProcessState {
+    pub fn new(liveness: Option<Liveness>) -> Self {
+        Self {
+            liveness: liveness.map(|liveness| {
+                let activity = Activity::new(&liveness, Instant::now());
+                (liveness, Mutex::new(activity))
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Record message received from process
+    pub fn seen(&self, msg: &Message) {
+        if msg.topic == HEADERS_ON_TOPIC {
+            self.headers.store(true, Ordering::Relaxed);
+        }
+        if let Some((_, activity)) = self.liveness.as_ref() {
+            activity.lock().unwrap().seen(msg, Instant::now());
+        }
+    }
+
+    /// Take subscriptions received before process got ready
+    pub fn take_subscriptions(&self) -> Vec<Message> {
+        std::mem::take(&mut *self.subscriptions.lock().unwrap())
+    }
+
+    /// Process or participant is running
+    pub fn is_running(&self) -> bool {
+        self.in_process || self.pid.load(Ordering::Relaxed) != 0
+    }
+
+    /// Start liveness monitoring, messages from process are observed from now on
+    pub fn arm(&self) {
+        if let Some((_, activity)) = self.liveness.as_ref() {
+            activity.lock().unwrap().reset(true, Instant::now());
+        }
+    }
+
+    /// Wait until process gets unhealthy, returns the reason
+    async fn unhealthy(&self) -> String {
+        let (liveness, activity) = match self.liveness.as_ref() {
+            Some(liveness) => liveness,
+            None => return future::pending().await,
+        };
+        loop {
+            tokio::time::delay_for(liveness.check_interval()).await;
+            if let Some(reason) = liveness.unhealthy(&activity.lock().unwrap(), Instant::now()) {
+                return reason;
+            }
+        }
+    }
+}
+
+/// Completes once orchestrator stops or process was stopped on its own
+async fn stopped(stop: &Stop, state: &ProcessState) {
+    while !stop.is_stopping() && !state.stopped.load(Ordering::Relaxed) {
+        tokio::time::delay_for(STOP_POLL_INTERVAL).await;
+    }
+}
+
+/// Supervision of a process: its output loggers and optional restart setup
+pub(crate) struct Supervisor<LF> {
+    pub loggers: Vec<LF>,
+    pub respawn: Option<Respawn<LF>>,
+    pub state: Arc<ProcessState>,
+    pub stop: Stop,
+}
+
+impl<LF> Supervisor<LF>
+where
+    LF: Future<Output = anyhow::Result<()>>,
+{
+    /// Run process and its logger until process exits and restart policy gives up
+    pub async fn run(self, process: Process) -> anyhow::Result<()> {
+        let Process { name, mut child } = process;
+        let Supervisor {
+            mut loggers,
+            mut respawn,
+            state,
+            stop,
+        } = self;
+        let mut recent: VecDeque<Instant> = VecDeque::new();
+
+        loop {
+            let pid = child.id();
+            state.pid.store(pid, Ordering::Relaxed);
+            let mut unhealthy = None;
+            let status = {
+                let exit = wait_exit(&name, child, loggers).fuse();
+                let watchdog = state.unhealthy().fuse();
+                pin_mut!(exit, watchdog);
+                select!(
+                    status = exit => status,
+                    reason = watchdog => {
+                        error!(target: &name, "unhealthy: {}, killing", reason);
+                        signal(pid, libc::SIGKILL);
+                        unhealthy = Some(reason);
+                        exit.await
+                    },
+                )
+            };
+            state.pid.store(0, Ordering::Relaxed);
+            warn!(target: &name, "exiting {:?}", status);
+            if let Ok(status) = status {
+                *state.exit.lock().unwrap() = Some(status);
+            }
+
+            if stop.is_stopping() || state.stopped.load(Ordering::Relaxed) {
+                info!(target: &name, "stopped");
+                return Ok(());
+            }
+            let respawn = match (respawn.as_mut(), unhealthy) {
+                (Some(respawn), _) if respawn.policy.should_restart(&status) => respawn,
+                (_, Some(reason)) => {
+                    return Err(OrchestratorError::Unhealthy {
+                        process: name,
+                        reason,
+                    }
+                    .into())
+                }
+                _ => return exit_result(&name, status),
+            };
+            if !state.connected.load(Ordering::Relaxed) {
+                return Err(OrchestratorError::HandshakeFailed {
+                    bridge: name,
+                    reason: "process exit before connecting".to_owned(),
+                }
+                .into());
+            }
+
+            let policy = &respawn.policy;
+            let restarts = policy.prune(&mut recent, Instant::now());
+            if restarts >= policy.max_restarts {
+                return Err(OrchestratorError::RestartsExhausted {
+                    process: name,
+                    restarts,
+                    window: policy.window,
+                }
+                .into());
+            }
+
+            let delay = policy.delay(restarts);
+            info!(target: &name, "restarting in {:?}", delay);
+            {
+                let backoff = tokio::time::delay_for(delay).fuse();
+                let stopped = stopped(&stop, &state).fuse();
+                pin_mut!(backoff, stopped);
+                select!(
+                    _ = backoff => {},
+                    _ = stopped => {},
+                );
+            }
+            if stop.is_stopping() || state.stopped.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            recent.push_back(Instant::now());
+            state.restarts.fetch_add(1, Ordering::Relaxed);
+            // Restarted process asks for headers again if it needs them
+            state.headers.store(false, Ordering::Relaxed);
+
+            let (c, l) = respawn.spawn(&name, &state, &stop)?;
+            child = c;
+            loggers = l;
+            if let Some((_, activity)) = state.liveness.as_ref() {
+                activity.lock().unwrap().reset(false, Instant::now());
+            }
+        }
+    }
+}
+
+/// Time given to loggers to process remaining output after process exit
+const LOGS_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Drive process loggers until process exits and its output is processed
+async fn wait_exit<LF>(
+    name: &str,
+    child: tokio::process::Child,
+    loggers: Vec<LF>,
+) -> std::io::Result<ExitStatus>
+where
+    LF: Future<Output = anyhow::Result<()>>,
+{
+    let mut child = child.fuse();
+    let mut loggers: FuturesUnordered<_> = loggers.into_iter().collect();
+    let status = loop {
+        select!(
+            status = child => break status,
+            res = loggers.select_next_some() => if let Err(err) = res {
+                debug!(target: name, "logs failure: {}", err);
+            },
+        )
+    };
+    // Output might still be buffered in pipes, or held open by process children
+    let drain = async {
+        while let Some(res) = loggers.next().await {
+            if let Err(err) = res {
+                debug!(target: name, "logs failure: {}", err);
+            }
+        }
+    };
+    if tokio::time::timeout(LOGS_DRAIN_TIMEOUT, drain)
+        .await
+        .is_err()
+    {
+        debug!(target: name, "output is still open after exit");
+    }
+    status
+}
+
+fn exit_result(name: &str, status: std::io::Result<ExitStatus>) -> anyhow::Result<()> {
+    match status {
+        Ok(n) if n.success() => Ok(()),
+        status => Err(OrchestratorError::ProcessExited {
+            process: name.to_owned(),
+            status: status.ok(),
+        }
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let policy =
+            RestartPolicy::always().backoff(Duration::from_millis(100), Duration::from_secs(1));
+        let delays: Vec<u64> = (0..6).map(|n| policy.delay(n).as_millis() as u64).collect();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1000, 1000]);
+        // Factor and delay overflows are capped as well
+        assert_eq!(policy.delay(40), Duration::from_secs(1));
+        assert_eq!(policy.delay(usize::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn backoff_max_is_at_least_initial() {
+        let policy =
+            RestartPolicy::always().backoff(Duration::from_secs(2), Duration::from_secs(1));
+        assert_eq!(policy.delay(0), Duration::from_secs(2));
+        assert_eq!(policy.delay(3), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn restarts_window_resets_backoff() {
+        let policy = RestartPolicy::on_failure()
+            .backoff(Duration::from_millis(100), Duration::from_secs(10))
+            .max_restarts(3, Duration::from_secs(60));
+        let start = Instant::now();
+        let mut restarts: VecDeque<Instant> = (0..3)
+            .map(|i| start + Duration::from_secs(i * 20))
+            .collect();
+        assert_eq!(
+            policy.prune(&mut restarts, start + Duration::from_secs(40)),
+            3
+        );
+        // The first restart falls out of window
+        let now = start + Duration::from_secs(70);
+        assert_eq!(policy.prune(&mut restarts, now), 2);
+        assert_eq!(policy.delay(2), Duration::from_millis(400));
+        // Process which stayed up longer than window starts from initial backoff
+        let now = start + Duration::from_secs(200);
+        assert_eq!(policy.prune(&mut restarts, now), 0);
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn unhealthy_process_is_killed() {
+        let liveness = Liveness::heartbeat(Duration::from_millis(10), 1);
+        let state = Arc::new(ProcessState::new(Some(liveness)));
+        state.arm();
+        let (_stop_tx, stop) = Stop::new();
+        let supervisor: Supervisor<future::Ready<anyhow::Result<()>>> = Supervisor {
+            loggers: Vec::new(),
+            respawn: None,
+            state: state.clone(),
+            stop,
+        };
+        let process = Process {
+            name: "sleep".to_owned(),
+            child: Command::new("sleep").arg("100").spawn().unwrap(),
+        };
+
+        let err = supervisor.run(process).await.unwrap_err();
+        match err.downcast_ref::<OrchestratorError>() {
+            Some(OrchestratorError::Unhealthy { process, reason }) => {
+                assert_eq!(process, "sleep");
+                assert_eq!(reason, "missed 1 heartbeats of 10ms");
+            }
+            _ => panic!("process was not unhealthy: {}", err),
+        }
+        let exit = state.exit.lock().unwrap().unwrap();
+        assert_eq!(
+            std::os::unix::process::ExitStatusExt::signal(&exit),
+            Some(libc::SIGKILL)
+        );
+        assert!(!state.is_running());
+    }
+}
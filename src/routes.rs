@@ -0,0 +1,826 @@
+//! Topic routes with wildcard patterns
+//!
+//! Topics are dot separated words, e.g. `metrics.cpu.load`.
+//! Route might be set up for topic pattern, where
+//! - `*` matches exactly one word: `metrics.*` matches `metrics.cpu`, but not `metrics.cpu.load`
+//! - `#` matches zero or more words: `sensors.#` matches `sensors`, `sensors.a` and `sensors.a.b`
+//!
+//! Exact topics are resolved with a single lookup, other topics are matched against patterns
+//! once and cached. Bridge routed from several topics or patterns receives every message once.
+//!
+//! Processes might subscribe to topics and unsubscribe from them at runtime
+//! with `Message::subscribe` and `Message::unsubscribe`, router applies them live.
+//!
+//! Routes of running router might be changed at once with `RouteTable`,
+//! every message is delivered either by routes before changes or by routes after them.
+//!
+//! Requests are routed as other messages with correlation id assigned by router,
+//! the first reply is delivered only to requester, see `AsyncClient::request`.
+
+use crate::dead_letter::{DeadLetterLog, DeadLetterPolicy};
+use crate::error::OrchestratorError;
+use crate::message::{Headers, Message, NO_RESPONDER_TOPIC, SUBSCRIBE_TOPIC, UNSUBSCRIBE_TOPIC};
+use crate::queue::{Overflow, Queue, QueueTx};
+use crate::restart::BridgeTx;
+use crate::shutdown::Stop;
+use crate::stats::{Registry, TopicCounters};
+use crate::topic::TopicPattern;
+use anyhow::anyhow;
+use log::{info, trace, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Topics matched against patterns are cached up to this number, then cache is reset
+const MATCHED_CACHE_CAPACITY: usize = 4096;
+/// Requests waiting for reply are kept up to this number, then the oldest are forgotten
+const PENDING_REQUESTS_CAPACITY: usize = 65536;
+
+/// Observer of messages delivered by router, it is removed once it returns false
+pub(crate) type Tap = Box<dyn FnMut(&Message, &Headers) -> bool + Send>;
+
+/// Changes of routes applied by running router at once, see `RouteTable::apply`
+#[derive(Clone, Debug, Default)]
+pub struct RouteChanges {
+    changes: Vec<RouteChange>,
+}
+
+#[derive(Clone, Debug)]
+struct RouteChange {
+    topic: String,
+    bridge: String,
+    route: bool,
+}
+
+impl RouteChanges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Route messages of `topic` or pattern to bridge `b_out`
+    pub fn route(mut self, topic: &str, b_out: &str) -> Self {
+        self.push(topic, b_out, true);
+        self
+    }
+
+    /// Stop routing messages of `topic` or pattern to bridge `b_out`
+    pub fn unroute(mut self, topic: &str, b_out: &str) -> Self {
+        self.push(topic, b_out, false);
+        self
+    }
+
+    fn push(&mut self, topic: &str, bridge: &str, route: bool) {
+        self.changes.push(RouteChange {
+            topic: topic.to_owned(),
+            bridge: bridge.to_owned(),
+            route,
+        });
+    }
+}
+
+/// Routes from topics and patterns to bridges, used by routers
+pub(crate) struct Routes {
+    /// Sender of every routed bridge, shared by all its routes
+    senders: Vec<Output>,
+    table: Table,
+    dead_letter: DeadLetterPolicy,
+    dead_letters: DeadLetterLog,
+    topics: TopicCounters,
+    /// Observers of messages matching any of their topics
+    taps: Vec<(Vec<TopicPattern>, Tap)>,
+    /// Requests waiting for reply by correlation id assigned by router
+    requests: BTreeMap<u64, Request>,
+    next_request: u64,
+}
+
+/// Request routed to responders
+struct Request {
+    /// Index of requester sender
+    requester: usize,
+    /// Correlation id set by requester
+    correlation_id: Option<u64>,
+}
+
+/// Recipient bridge of routes
+pub(crate) enum Output {
+    /// Messages are sent right away
+    Bridge(BridgeTx),
+    /// Messages are queued, queue is sent to bridge by separate thread
+    Queue(Arc<Queue>, QueueTx),
+}
+
+impl Output {
+    fn name(&self) -> &str {
+        match self {
+            Output::Bridge(tx) => &tx.name,
+            Output::Queue(queue, _) => queue.name(),
+        }
+    }
+
+    fn is_stopped(&self) -> bool {
+        match self {
+            Output::Bridge(tx) => tx.is_stopped(),
+            Output::Queue(..) => false,
+        }
+    }
+
+    fn send(
+        &mut self,
+        msg: Message,
+        headers: &Headers,
+        stop: &Stop,
+    ) -> Result<(), ipc_channel::Error> {
+        match self {
+            Output::Bridge(tx) => tx.send(msg, Some(headers)),
+            Output::Queue(queue, tx) => {
+                queue.push(tx, msg, headers.clone(), stop);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Recipients of topics as indices of senders
+#[derive(Default)]
+struct Table {
+    /// Routes of exact topics
+    topics: HashMap<String, Vec<usize>>,
+    patterns: Vec<(TopicPattern, Vec<usize>)>,
+    /// Recipients of exact topics, including bridges routed from matching patterns
+    exact: HashMap<String, Vec<usize>>,
+    /// Recipients of other topics matched against patterns
+    matched: HashMap<String, Vec<usize>>,
+}
+
+impl Routes {
+    pub fn new(stats: &Registry) -> Self {
+        Self {
+            senders: Vec::new(),
+            table: Table::default(),
+            dead_letter: DeadLetterPolicy::default(),
+            dead_letters: stats.dead_letters().clone(),
+            topics: stats.topics(),
+            taps: Vec::new(),
+            requests: BTreeMap::new(),
+            next_request: 0,
+        }
+    }
+
+    /// Bridge `name` already has sender in routes
+    pub fn has_bridge(&self, name: &str) -> bool {
+        self.senders.iter().any(|tx| tx.name() == name)
+    }
+
+    /// Add sender of bridge, which then might be routed from topics
+    pub fn add_bridge(&mut self, tx: BridgeTx) {
+        self.senders.push(Output::Bridge(tx));
+    }
+
+    /// Add bridge joining running router
+    pub fn join(&mut self, output: Output) {
+        info!("`{}` joined router", output.name());
+        self.senders.push(output);
+    }
+
+    /// Remove bridge `name` with all its routes, its requests waiting for reply are forgotten
+    pub fn leave(&mut self, name: &str) {
+        let idx = match self.senders.iter().position(|tx| tx.name() == name) {
+            Some(idx) => idx,
+            None => return,
+        };
+        info!("`{}` left router", name);
+        self.senders.remove(idx);
+        self.table.remove(idx);
+        self.requests.retain(|_, request| request.requester != idx);
+        for request in self.requests.values_mut() {
+            if request.requester > idx {
+                request.requester -= 1;
+            }
+        }
+    }
+
+    /// Put messages to every bridge into bounded queue,
+    /// returns queues with bridges they should be sent to
+    pub fn queue(&mut self, capacity: usize, overflow: Overflow) -> Vec<(Arc<Queue>, BridgeTx)> {
+        let mut queues = Vec::new();
+        for output in self.senders.iter_mut() {
+            let (queue, queue_tx) = Queue::new(output.name(), capacity, overflow);
+            if let Output::Bridge(tx) =
+                std::mem::replace(output, Output::Queue(queue.clone(), queue_tx))
+            {
+                queues.push((queue, tx));
+            }
+        }
+        queues
+    }
+
+    fn bridge(&self, name: &str) -> anyhow::Result<usize> {
+        self.senders
+            .iter()
+            .position(|tx| tx.name() == name)
+            .ok_or_else(|| anyhow!("bridge `{}` was not added to routes", name))
+    }
+
+    /// Route messages of `topic` to bridge `name` added with `add_bridge`
+    pub fn route(&mut self, topic: TopicPattern, name: &str) -> anyhow::Result<()> {
+        let idx = self.bridge(name)?;
+        self.table.route(topic, idx);
+        Ok(())
+    }
+
+    /// Stop routing messages of `topic` to bridge `name`
+    pub fn unroute(&mut self, topic: &TopicPattern, name: &str) -> anyhow::Result<()> {
+        let idx = self.bridge(name)?;
+        self.table.unroute(topic, idx);
+        Ok(())
+    }
+
+    /// Apply all the `changes` or none of them if any is invalid
+    pub fn apply(&mut self, changes: RouteChanges) -> anyhow::Result<()> {
+        let changes = changes
+            .changes
+            .into_iter()
+            .map(|change| {
+                let topic = TopicPattern::new(&change.topic)?;
+                Ok((topic, self.bridge(&change.bridge)?, change))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        for (topic, idx, change) in changes {
+            if change.route {
+                info!("routing {} -> {}", topic, change.bridge);
+                self.table.route(topic, idx);
+            } else {
+                info!("unrouting {} -> {}", topic, change.bridge);
+                self.table.unroute(&topic, idx);
+            }
+        }
+        Ok(())
+    }
+
+    /// Observe messages of any of `topics` with `tap`, delivery is not affected
+    pub fn tap(&mut self, topics: Vec<TopicPattern>, tap: Tap) {
+        self.taps.push((topics, tap));
+    }
+
+    /// Handle messages without recipients with `policy`, bridge should be added with `add_bridge`
+    pub fn dead_letter(&mut self, policy: DeadLetterPolicy) -> anyhow::Result<()> {
+        match &policy {
+            DeadLetterPolicy::Bridge(name) => {
+                self.bridge(name)?;
+            }
+            DeadLetterPolicy::Topic(topic) if !TopicPattern::new(topic)?.is_exact() => {
+                return Err(anyhow!("dead letter topic `{}` is a pattern", topic));
+            }
+            _ => {}
+        }
+        self.dead_letter = policy;
+        Ok(())
+    }
+
+    /// Send message to every recipient of its topic, handling messages without recipients
+    /// according to dead letter policy. Failures are ignored once shutdown started,
+    /// `headers` are sent to recipients which asked for them
+    pub fn deliver(
+        &mut self,
+        msg: Message,
+        headers: &Headers,
+        stop: &Stop,
+    ) -> Result<(), OrchestratorError> {
+        if msg.topic == SUBSCRIBE_TOPIC || msg.topic == UNSUBSCRIBE_TOPIC {
+            self.subscription(&msg, headers);
+            return Ok(());
+        }
+        self.topics.record(&msg);
+        if !self.taps.is_empty() && !msg.is_control() {
+            self.taps.retain_mut(|(topics, tap)| {
+                !topics.iter().any(|topic| topic.matches(&msg.topic)) || tap(&msg, headers)
+            });
+        }
+        if headers.request {
+            return self.request(msg, headers, stop);
+        }
+        if msg.is_reply() {
+            return self.reply(msg, headers, stop);
+        }
+        let recipients = self.table.recipients(&msg.topic);
+        if !recipients.is_empty() {
+            return send(&mut self.senders, recipients, msg, headers, stop);
+        }
+        let bridge = headers.source.as_deref();
+        let log = self.dead_letter == DeadLetterPolicy::Log;
+        self.dead_letters.record(&msg, bridge, log);
+        match &self.dead_letter {
+            DeadLetterPolicy::Fail => Err(OrchestratorError::UnroutedTopic {
+                bridge: bridge.map(str::to_owned),
+                topic: msg.topic,
+            }),
+            DeadLetterPolicy::Drop | DeadLetterPolicy::Log => Ok(()),
+            DeadLetterPolicy::Bridge(name) => {
+                let idx = self.senders.iter().position(|tx| tx.name() == name);
+                send(&mut self.senders, idx.as_slice(), msg, headers, stop)
+            }
+            DeadLetterPolicy::Topic(topic) => {
+                let recipients = self.table.recipients(topic);
+                if recipients.is_empty() {
+                    trace!("dead letter topic {} has no recipients", topic);
+                    return Ok(());
+                }
+                send(&mut self.senders, recipients, msg, headers, stop)
+            }
+        }
+    }
+}
+
+impl Routes {
+    /// Send message to bridge `name` only, message to bridge which left is dropped
+    pub fn send_to(
+        &mut self,
+        name: &str,
+        msg: Message,
+        headers: &Headers,
+        stop: &Stop,
+    ) -> Result<(), OrchestratorError> {
+        let idx = match self.senders.iter().position(|tx| tx.name() == name) {
+            Some(idx) => idx,
+            None => {
+                warn!("dropped message to `{}`, it is not routed", name);
+                return Ok(());
+            }
+        };
+        self.topics.record(&msg);
+        send(&mut self.senders, &[idx], msg, headers, stop)
+    }
+
+    /// Apply subscribe or unsubscribe message of source bridge,
+    /// invalid subscriptions are logged and ignored, so that process cannot stop router
+    pub fn subscription(&mut self, msg: &Message, headers: &Headers) {
+        let source = headers.source.as_deref().unwrap_or_default();
+        let subscribe = msg.topic == SUBSCRIBE_TOPIC;
+        let applied = std::str::from_utf8(&msg.data)
+            .map_err(anyhow::Error::from)
+            .and_then(TopicPattern::new)
+            .and_then(|topic| match subscribe {
+                true => {
+                    info!("`{}` subscribed to {}", source, topic);
+                    self.route(topic, source)
+                }
+                false => {
+                    info!("`{}` unsubscribed from {}", source, topic);
+                    self.unroute(&topic, source)
+                }
+            });
+        if let Err(err) = applied {
+            warn!("ignored {} of `{}`: {}", msg.topic, source, err);
+        }
+    }
+
+    /// Route request with correlation id of router, requester is told when there is no responder
+    fn request(
+        &mut self,
+        msg: Message,
+        headers: &Headers,
+        stop: &Stop,
+    ) -> Result<(), OrchestratorError> {
+        let source = headers.source.as_deref().unwrap_or_default();
+        let requester = match self.senders.iter().position(|tx| tx.name() == source) {
+            Some(requester) => requester,
+            None => {
+                warn!("dropped request from `{}`, it cannot receive reply", source);
+                return Ok(());
+            }
+        };
+        let recipients = self.table.recipients(&msg.topic);
+        if recipients.is_empty() {
+            trace!("request to topic {} has no responder", msg.topic);
+            let reply = Message {
+                topic: NO_RESPONDER_TOPIC.to_owned(),
+                data: msg.topic.into_bytes(),
+            };
+            let headers = Headers {
+                correlation_id: headers.correlation_id,
+                ..Default::default()
+            };
+            return send(&mut self.senders, &[requester], reply, &headers, stop);
+        }
+        if self.requests.len() >= PENDING_REQUESTS_CAPACITY {
+            self.requests.pop_first();
+        }
+        self.next_request += 1;
+        self.requests.insert(
+            self.next_request,
+            Request {
+                requester,
+                correlation_id: headers.correlation_id,
+            },
+        );
+        let headers = Headers {
+            correlation_id: Some(self.next_request),
+            ..headers.clone()
+        };
+        send(&mut self.senders, recipients, msg, &headers, stop)
+    }
+
+    /// Deliver reply to requester, replies to unknown or answered requests are dropped
+    fn reply(
+        &mut self,
+        msg: Message,
+        headers: &Headers,
+        stop: &Stop,
+    ) -> Result<(), OrchestratorError> {
+        let request = match headers
+            .correlation_id
+            .and_then(|id| self.requests.remove(&id))
+        {
+            Some(request) => request,
+            None => {
+                trace!("dropped reply from {:?} to unknown request", headers.source);
+                return Ok(());
+            }
+        };
+        let headers = Headers {
+            correlation_id: request.correlation_id,
+            ..headers.clone()
+        };
+        send(&mut self.senders, &[request.requester], msg, &headers, stop)
+    }
+}
+
+impl Table {
+    fn route(&mut self, topic: TopicPattern, idx: usize) {
+        let recipients = if topic.is_exact() {
+            self.topics.entry(topic.pattern).or_default()
+        } else {
+            match self.patterns.iter().position(|(p, _)| *p == topic) {
+                Some(i) => &mut self.patterns[i].1,
+                None => {
+                    self.patterns.push((topic, Vec::new()));
+                    &mut self.patterns.last_mut().unwrap().1
+                }
+            }
+        };
+        if !recipients.contains(&idx) {
+            recipients.push(idx);
+        }
+        self.rebuild();
+    }
+
+    fn unroute(&mut self, topic: &TopicPattern, idx: usize) {
+        let recipients = if topic.is_exact() {
+            self.topics.get_mut(&topic.pattern)
+        } else {
+            self.patterns
+                .iter_mut()
+                .find(|(p, _)| p == topic)
+                .map(|(_, recipients)| recipients)
+        };
+        if let Some(recipients) = recipients {
+            recipients.retain(|i| *i != idx);
+        }
+        self.topics.retain(|_, recipients| !recipients.is_empty());
+        self.patterns
+            .retain(|(_, recipients)| !recipients.is_empty());
+        self.rebuild();
+    }
+
+    /// Remove sender `idx` from all the routes, senders after it are shifted down
+    fn remove(&mut self, idx: usize) {
+        let routes = self.topics.values_mut();
+        let patterns = self.patterns.iter_mut().map(|(_, recipients)| recipients);
+        for recipients in routes.chain(patterns) {
+            recipients.retain(|i| *i != idx);
+            for i in recipients.iter_mut() {
+                if *i > idx {
+                    *i -= 1;
+                }
+            }
+        }
+        self.topics.retain(|_, recipients| !recipients.is_empty());
+        self.patterns
+            .retain(|(_, recipients)| !recipients.is_empty());
+        self.rebuild();
+    }
+
+    /// Recompute recipients of exact topics and reset cache of matched topics
+    fn rebuild(&mut self) {
+        self.exact = self
+            .topics
+            .keys()
+            .map(|topic| (topic.clone(), self.resolve(topic)))
+            .collect();
+        self.matched.clear();
+    }
+
+    /// Recipients of `topic`, exact topics are resolved with single lookup
+    fn recipients(&mut self, topic: &str) -> &[usize] {
+        if self.exact.contains_key(topic) {
+            return &self.exact[topic];
+        }
+        if self.patterns.is_empty() {
+            return &[];
+        }
+        if !self.matched.contains_key(topic) {
+            if self.matched.len() >= MATCHED_CACHE_CAPACITY {
+                self.matched.clear();
+            }
+            let recipients = self.resolve(topic);
+            self.matched.insert(topic.to_owned(), recipients);
+        }
+        &self.matched[topic]
+    }
+
+    /// Indices of senders which should receive messages of `topic`
+    fn resolve(&self, topic: &str) -> Vec<usize> {
+        let mut recipients = self.topics.get(topic).cloned().unwrap_or_default();
+        for (pattern, senders) in self.patterns.iter() {
+            if pattern.matches(topic) {
+                for idx in senders {
+                    if !recipients.contains(idx) {
+                        recipients.push(*idx);
+                    }
+                }
+            }
+        }
+        recipients
+    }
+}
+
+/// Send message to `recipients`, cloning it for all but the last one
+fn send(
+    senders: &mut [Output],
+    recipients: &[usize],
+    msg: Message,
+    headers: &Headers,
+    stop: &Stop,
+) -> Result<(), OrchestratorError> {
+    trace!(
+        "sending message from topic {} to {} senders",
+        msg.topic,
+        recipients.len()
+    );
+    let (last, recipients) = match recipients.split_last() {
+        Some(recipients) => recipients,
+        None => return Ok(()),
+    };
+    for idx in recipients {
+        let tx = &mut senders[*idx];
+        if let Err(err) = tx.send(msg.clone(), headers, stop) {
+            if !stop.is_stopping() && !tx.is_stopped() {
+                return Err(OrchestratorError::send(tx.name(), Some(&msg.topic), err));
+            }
+        }
+    }
+    let topic = msg.topic.clone(); // TODO - see if it impacting perf
+    let last = &mut senders[*last];
+    if let Err(err) = last.send(msg, headers, stop) {
+        if !stop.is_stopping() && !last.is_stopped() {
+            return Err(OrchestratorError::send(last.name(), Some(&topic), err));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dead_letter::DeadLetterPolicy;
+    use crate::message::REPLY_TOPIC;
+    use crate::restart::ProcessState;
+    use crate::stats::Counter;
+    use crate::Receiver;
+    use ipc_channel::ipc;
+    use std::sync::atomic::Ordering;
+
+    fn pattern(pattern: &str) -> TopicPattern {
+        TopicPattern::new(pattern).unwrap()
+    }
+
+    /// Add bridge `name` which asked for headers, returns receiving end of its channel
+    fn add_bridge(routes: &mut Routes, name: &str) -> Receiver {
+        let (tx, rx) = ipc::channel().unwrap();
+        let state = ProcessState::default();
+        state.headers.store(true, Ordering::Relaxed);
+        let traffic = Arc::new(Counter::default());
+        routes.add_bridge(BridgeTx::new(
+            name.to_owned(),
+            tx,
+            None,
+            Some(Arc::new(state)),
+            traffic,
+        ));
+        rx
+    }
+
+    /// Message received by bridge with its headers, None when bridge received nothing
+    fn received(rx: &Receiver) -> Option<(Message, Headers)> {
+        let (msg, headers) = rx.try_recv().ok()?.split_headers();
+        Some((msg, headers.unwrap_or_default()))
+    }
+
+    fn message(topic: &str) -> Message {
+        Message {
+            topic: topic.to_owned(),
+            data: topic.as_bytes().to_vec(),
+        }
+    }
+
+    fn request(source: &str, correlation_id: u64) -> Headers {
+        Headers {
+            source: Some(source.to_owned()),
+            correlation_id: Some(correlation_id),
+            request: true,
+            ..Default::default()
+        }
+    }
+
+    fn reply(source: &str, correlation_id: u64) -> (Message, Headers) {
+        let headers = Headers {
+            source: Some(source.to_owned()),
+            correlation_id: Some(correlation_id),
+            ..Default::default()
+        };
+        (message(REPLY_TOPIC), headers)
+    }
+
+    #[test]
+    fn recipients_of_topics_and_patterns() {
+        let mut table = Table::default();
+        table.route(pattern("metrics.cpu"), 0);
+        table.route(pattern("metrics.*"), 1);
+        table.route(pattern("metrics.#"), 0);
+        table.route(pattern("metrics.*"), 1);
+        assert_eq!(table.recipients("metrics.cpu"), &[0, 1]);
+        assert_eq!(table.recipients("metrics.mem"), &[1, 0]);
+        assert_eq!(table.recipients("metrics.cpu.load"), &[0]);
+        assert!(table.recipients("logs").is_empty());
+    }
+
+    #[test]
+    fn route_changes_reset_cache() {
+        let mut table = Table::default();
+        table.route(pattern("metrics.*"), 0);
+        assert_eq!(table.recipients("metrics.mem"), &[0]);
+        assert!(table.matched.contains_key("metrics.mem"));
+
+        table.route(pattern("metrics.#"), 1);
+        assert!(table.matched.is_empty());
+        assert_eq!(table.recipients("metrics.mem"), &[0, 1]);
+
+        table.unroute(&pattern("metrics.*"), 0);
+        assert_eq!(table.recipients("metrics.mem"), &[1]);
+
+        // Senders after removed one are shifted down
+        table.route(pattern("metrics.mem"), 2);
+        assert_eq!(table.recipients("metrics.mem"), &[2, 1]);
+        table.remove(1);
+        assert_eq!(table.recipients("metrics.mem"), &[1]);
+        assert!(table.patterns.is_empty());
+        assert!(table.recipients("metrics.cpu").is_empty());
+    }
+
+    #[test]
+    fn matched_cache_is_bounded() {
+        let mut table = Table::default();
+        table.route(pattern("metrics.#"), 0);
+        for i in 0..=MATCHED_CACHE_CAPACITY {
+            assert_eq!(table.recipients(&format!("metrics.{}", i)), &[0]);
+        }
+        assert_eq!(table.matched.len(), 1);
+    }
+
+    #[test]
+    fn reply_gets_correlation_id_of_requester() {
+        let (_stop_tx, stop) = Stop::new();
+        let mut routes = Routes::new(&Registry::default());
+        let requester = add_bridge(&mut routes, "requester");
+        let responder = add_bridge(&mut routes, "responder");
+        routes.route(pattern("double"), "responder").unwrap();
+
+        let requests = vec![(7, 1), (7, 2), (3, 3)];
+        for (correlation_id, routed_id) in requests {
+            routes
+                .deliver(
+                    message("double"),
+                    &request("requester", correlation_id),
+                    &stop,
+                )
+                .unwrap();
+            let (_, headers) = received(&responder).unwrap();
+            assert!(headers.request);
+            assert_eq!(headers.correlation_id, Some(routed_id));
+        }
+
+        let (msg, headers) = reply("responder", 3);
+        routes.deliver(msg, &headers, &stop).unwrap();
+        let (msg, headers) = received(&requester).unwrap();
+        assert!(msg.is_reply());
+        assert_eq!(headers.correlation_id, Some(3));
+        assert_eq!(headers.source.as_deref(), Some("responder"));
+
+        // Request is answered once, replies to unknown requests are dropped
+        for id in [3, 42] {
+            let (msg, headers) = reply("responder", id);
+            routes.deliver(msg, &headers, &stop).unwrap();
+        }
+        assert!(received(&requester).is_none());
+        assert_eq!(routes.requests.len(), 2);
+    }
+
+    #[test]
+    fn requester_follows_bridges_leaving() {
+        let (_stop_tx, stop) = Stop::new();
+        let mut routes = Routes::new(&Registry::default());
+        let _left = add_bridge(&mut routes, "left");
+        let requester = add_bridge(&mut routes, "requester");
+        let responder = add_bridge(&mut routes, "responder");
+        routes.route(pattern("double"), "responder").unwrap();
+
+        routes
+            .deliver(message("double"), &request("left", 1), &stop)
+            .unwrap();
+        routes
+            .deliver(message("double"), &request("requester", 2), &stop)
+            .unwrap();
+        let (_, left_request) = received(&responder).unwrap();
+        let (_, request) = received(&responder).unwrap();
+
+        routes.leave("left");
+        assert_eq!(routes.requests.len(), 1);
+        assert_eq!(routes.requests.values().next().unwrap().requester, 0);
+
+        // Request of bridge which left is forgotten
+        let (msg, headers) = reply("responder", left_request.correlation_id.unwrap());
+        routes.deliver(msg, &headers, &stop).unwrap();
+        assert!(received(&requester).is_none());
+
+        let (msg, headers) = reply("responder", request.correlation_id.unwrap());
+        routes.deliver(msg, &headers, &stop).unwrap();
+        let (msg, headers) = received(&requester).unwrap();
+        assert!(msg.is_reply());
+        assert_eq!(headers.correlation_id, Some(2));
+    }
+
+    #[test]
+    fn request_without_responder_is_answered_by_router() {
+        let (_stop_tx, stop) = Stop::new();
+        let mut routes = Routes::new(&Registry::default());
+        let requester = add_bridge(&mut routes, "requester");
+
+        routes
+            .deliver(message("double"), &request("requester", 5), &stop)
+            .unwrap();
+        let (msg, headers) = received(&requester).unwrap();
+        assert_eq!(msg.topic, NO_RESPONDER_TOPIC);
+        assert_eq!(msg.data, b"double");
+        assert_eq!(headers.correlation_id, Some(5));
+        assert!(routes.requests.is_empty());
+    }
+
+    #[test]
+    fn unrouted_topic_is_handled_by_dead_letter_policy() {
+        let (_stop_tx, stop) = Stop::new();
+        let deliver_unrouted = |policy: Option<DeadLetterPolicy>| {
+            let mut routes = Routes::new(&Registry::default());
+            let logger = add_bridge(&mut routes, "logger");
+            let recipient = add_bridge(&mut routes, "recipient");
+            routes.route(pattern("unrouted"), "recipient").unwrap();
+            if let Some(policy) = policy {
+                routes.dead_letter(policy).unwrap();
+            }
+            let headers = Headers {
+                source: Some("source".to_owned()),
+                ..Default::default()
+            };
+            let delivered = routes.deliver(message("misspelled"), &headers, &stop);
+            assert_eq!(routes.dead_letters.count(), 1);
+            let received = |rx| received(rx).map(|(msg, _)| msg.topic);
+            (delivered, received(&logger), received(&recipient))
+        };
+
+        match deliver_unrouted(Some(DeadLetterPolicy::Fail)) {
+            (Err(OrchestratorError::UnroutedTopic { bridge, topic }), None, None) => {
+                assert_eq!(bridge.as_deref(), Some("source"));
+                assert_eq!(topic, "misspelled");
+            }
+            res => panic!("unrouted topic did not fail router: {:?}", res),
+        }
+        for policy in [None, Some(DeadLetterPolicy::Drop)] {
+            assert!(matches!(deliver_unrouted(policy), (Ok(()), None, None)));
+        }
+        let logged = deliver_unrouted(Some(DeadLetterPolicy::Bridge("logger".to_owned())));
+        assert!(matches!(logged, (Ok(()), Some(topic), None) if topic == "misspelled"));
+        let forwarded = deliver_unrouted(Some(DeadLetterPolicy::Topic("unrouted".to_owned())));
+        assert!(matches!(forwarded, (Ok(()), None, Some(topic)) if topic == "misspelled"));
+    }
+
+    #[test]
+    fn dead_letter_policy_should_be_valid() {
+        let mut routes = Routes::new(&Registry::default());
+        let _logger = add_bridge(&mut routes, "logger");
+        assert!(routes
+            .dead_letter(DeadLetterPolicy::Bridge("missing".to_owned()))
+            .is_err());
+        assert!(routes
+            .dead_letter(DeadLetterPolicy::Topic("metrics.*".to_owned()))
+            .is_err());
+        assert_eq!(routes.dead_letter, DeadLetterPolicy::Log);
+    }
+}
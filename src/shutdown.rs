@@ -0,0 +1,78 @@
+//! Graceful shutdown of orchestrated processes and routing threads
+//!
+//! Shutdown sends SIGTERM to every process, waits for processes to exit within grace period,
+//! kills the rest with SIGKILL, then stops routing threads.
+//! Processes are not restarted once shutdown started.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use tokio::process::Command;
+//! use ipc_orchestrator::orchestrator;
+//!
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//!     let mut orchestrator = orchestrator().ipc(false);
+//!     let mut cmd = Command::new("sleep");
+//!     cmd.arg("100");
+//!     orchestrator.start("sleep", &mut cmd).unwrap();
+//!     let orchestra = orchestrator.connect().await.unwrap();
+//!     // any future might trigger shutdown, e.g. tokio::signal::ctrl_c()
+//!     let trigger = tokio::time::delay_for(Duration::from_millis(100));
+//!     let report = orchestra.run_until(trigger, Duration::from_secs(1)).await.unwrap();
+//!     assert!(report.killed.is_empty());
+//!     assert!(report.statuses["sleep"].is_some());
+//! # });
+//! ```
+
+use crossbeam::channel::{self, TryRecvError};
+use std::collections::HashMap;
+use std::process::ExitStatus;
+
+/// Shutdown signal shared by supervisors and routing threads,
+/// triggered by dropping its sender
+#[derive(Clone, Debug)]
+pub(crate) struct Stop(channel::Receiver<()>);
+
+impl Stop {
+    pub fn new() -> (channel::Sender<()>, Stop) {
+        let (tx, rx) = channel::bounded(0);
+        (tx, Stop(rx))
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        matches!(self.0.try_recv(), Err(TryRecvError::Disconnected))
+    }
+
+    /// Receiver which disconnects on shutdown, to be used in `crossbeam::select!`
+    pub fn receiver(&self) -> &channel::Receiver<()> {
+        &self.0
+    }
+}
+
+/// Report on processes exit after shutdown
+#[derive(Debug, Default)]
+pub struct ExitReport {
+    /// Last exit status of every process, None if process did not exit or status is unknown
+    pub statuses: HashMap<String, Option<ExitStatus>>,
+    /// Processes which did not exit within grace period and were killed
+    pub killed: Vec<String>,
+    /// Routing threads failures
+    pub errors: Vec<String>,
+}
+
+impl ExitReport {
+    /// All the processes exit successfully and routing threads stopped without failures
+    pub fn success(&self) -> bool {
+        self.errors.is_empty()
+            && self
+                .statuses
+                .values()
+                .all(|status| status.is_some_and(|s| s.success()))
+    }
+}
+
+/// Send signal to process, pid 0 stands for not running process
+pub(crate) fn signal(pid: u32, signal: libc::c_int) -> bool {
+    pid != 0 && unsafe { libc::kill(pid as libc::pid_t, signal) } == 0
+}
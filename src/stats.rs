@@ -0,0 +1,233 @@
+//! Traffic statistics of routers and pipes
+//!
+//! Routing threads count messages and bytes of message data
+//! per topic, per source bridge and per destination bridge, together with send errors,
+//! recipient queue depths and dead letters.
+//! Counters are atomic, so that reading statistics does not stall routing.
+//!
+//! `ConnectedOrchestrator::stats()` returns snapshot of counters,
+//! `ConnectedOrchestrator::log_stats()` logs it periodically.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use tokio::process::Command;
+//! use ipc_orchestrator::orchestrator;
+//!
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//!     let mut orchestrator = orchestrator().ipc(false);
+//!     orchestrator.start("echo", &mut Command::new("echo")).unwrap();
+//!     let orchestra = orchestrator.connect().await.unwrap();
+//!     orchestra.log_stats(Duration::from_secs(10));
+//!     let stats = orchestra.stats();
+//!     assert!(stats.topics.is_empty());
+//!     println!("{}", stats);
+//!     # orchestra.shutdown(Duration::from_secs(1)).await.unwrap();
+//! # });
+//! ```
+
+use crate::dead_letter::DeadLetterLog;
+use crate::message::Message;
+use crate::queue::{Queue, QueueStats};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Messages passed through topic or bridge
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Traffic {
+    pub messages: u64,
+    /// Size of message data in bytes
+    pub bytes: u64,
+    /// Failed sends, counted for destination bridges only
+    pub errors: u64,
+}
+
+/// Snapshot of routing statistics
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    /// Traffic per topic
+    pub topics: HashMap<String, Traffic>,
+    /// Traffic received from bridges
+    pub sources: HashMap<String, Traffic>,
+    /// Traffic sent to bridges
+    pub destinations: HashMap<String, Traffic>,
+    /// Recipient queues by bridge name, see `pipe_routes_via_queues`
+    pub queues: HashMap<String, QueueStats>,
+    /// Messages to topics without recipients
+    pub dead_letters: u64,
+}
+
+/// Single line summary, entries are sorted by name
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn traffic(traffic: &HashMap<String, Traffic>) -> String {
+            let entries: BTreeMap<_, _> = traffic.iter().collect();
+            let entries: Vec<String> = entries
+                .into_iter()
+                .map(|(name, t)| match t.errors {
+                    0 => format!("{} {} msgs {} B", name, t.messages, t.bytes),
+                    errors => format!(
+                        "{} {} msgs {} B {} errors",
+                        name, t.messages, t.bytes, errors
+                    ),
+                })
+                .collect();
+            entries.join(", ")
+        }
+        write!(
+            f,
+            "topics: {}; sources: {}; destinations: {}",
+            traffic(&self.topics),
+            traffic(&self.sources),
+            traffic(&self.destinations)
+        )?;
+        if !self.queues.is_empty() {
+            let queues: BTreeMap<_, _> = self.queues.iter().collect();
+            let queues: Vec<String> = queues
+                .into_iter()
+                .map(|(name, q)| {
+                    format!("{} {}/{} dropped {}", name, q.depth, q.capacity, q.dropped)
+                })
+                .collect();
+            write!(f, "; queues: {}", queues.join(", "))?;
+        }
+        write!(f, "; dead letters: {}", self.dead_letters)
+    }
+}
+
+/// Traffic counter shared by routing thread and orchestrator
+#[derive(Debug, Default)]
+pub(crate) struct Counter {
+    messages: AtomicU64,
+    bytes: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl Counter {
+    /// Count message with `bytes` of data
+    pub fn record(&self, bytes: usize) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn traffic(&self) -> Traffic {
+        Traffic {
+            messages: self.messages.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+type Counters = Mutex<HashMap<String, Arc<Counter>>>;
+
+/// All the counters of orchestrator
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Registry(Arc<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    topics: Counters,
+    sources: Counters,
+    destinations: Counters,
+    queues: Mutex<Vec<Arc<Queue>>>,
+    dead_letters: DeadLetterLog,
+}
+
+fn counter(counters: &Counters, name: &str) -> Arc<Counter> {
+    counters
+        .lock()
+        .unwrap()
+        .entry(name.to_owned())
+        .or_default()
+        .clone()
+}
+
+fn snapshot(counters: &Counters) -> HashMap<String, Traffic> {
+    counters
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, counter)| (name.clone(), counter.traffic()))
+        .collect()
+}
+
+impl Registry {
+    pub fn source(&self, bridge: &str) -> Arc<Counter> {
+        counter(&self.0.sources, bridge)
+    }
+
+    pub fn destination(&self, bridge: &str) -> Arc<Counter> {
+        counter(&self.0.destinations, bridge)
+    }
+
+    /// Counters of topics for a routing thread
+    pub fn topics(&self) -> TopicCounters {
+        TopicCounters {
+            registry: self.clone(),
+            counters: HashMap::new(),
+        }
+    }
+
+    pub fn add_queue(&self, queue: Arc<Queue>) {
+        self.0.queues.lock().unwrap().push(queue);
+    }
+
+    pub fn remove_queue(&self, name: &str) {
+        self.0
+            .queues
+            .lock()
+            .unwrap()
+            .retain(|queue| queue.name() != name);
+    }
+
+    pub fn queues(&self) -> HashMap<String, QueueStats> {
+        self.0
+            .queues
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|queue| (queue.name().to_owned(), queue.stats()))
+            .collect()
+    }
+
+    pub fn dead_letters(&self) -> &DeadLetterLog {
+        &self.0.dead_letters
+    }
+
+    pub fn snapshot(&self) -> Stats {
+        Stats {
+            topics: snapshot(&self.0.topics),
+            sources: snapshot(&self.0.sources),
+            destinations: snapshot(&self.0.destinations),
+            queues: self.queues(),
+            dead_letters: self.0.dead_letters.count(),
+        }
+    }
+}
+
+/// Topic counters cached by routing thread, so that shared registry is locked only for new topics
+pub(crate) struct TopicCounters {
+    registry: Registry,
+    counters: HashMap<String, Arc<Counter>>,
+}
+
+impl TopicCounters {
+    pub fn record(&mut self, msg: &Message) {
+        match self.counters.get(&msg.topic) {
+            Some(counter) => counter.record(msg.data.len()),
+            None => {
+                let counter = counter(&self.registry.0.topics, &msg.topic);
+                counter.record(msg.data.len());
+                self.counters.insert(msg.topic.clone(), counter);
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Topic patterns with wildcards, matched by router and by `AsyncClient` subscriptions
+//!
+//! Topics are dot separated words, e.g. `metrics.cpu.load`, pattern might contain
+//! - `*` matching exactly one word: `metrics.*` matches `metrics.cpu`, but not `metrics.cpu.load`
+//! - `#` matching zero or more words: `sensors.#` matches `sensors`, `sensors.a` and `sensors.a.b`
+//!
+//! # Example
+//!
+//! ```
+//! use ipc_orchestrator::TopicPattern;
+//!
+//! let metrics = TopicPattern::new("metrics.*").unwrap();
+//! assert!(metrics.matches("metrics.cpu"));
+//! assert!(!metrics.matches("metrics.cpu.load"));
+//! let sensors = TopicPattern::new("sensors.#").unwrap();
+//! assert!(sensors.matches("sensors"));
+//! assert!(sensors.matches("sensors.room.temperature"));
+//! assert!(TopicPattern::new("metrics.cpu*").is_err());
+//! ```
+
+use anyhow::anyhow;
+use std::fmt;
+
+/// Topic or topic pattern with `*` and `#` wildcards
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TopicPattern {
+    pub(crate) pattern: String,
+    words: Vec<Word>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Word {
+    Exact(String),
+    /// `*`
+    One,
+    /// `#`
+    Any,
+}
+
+impl TopicPattern {
+    /// Parse topic `pattern`, wildcards should be whole words, e.g. `metrics.*`
+    pub fn new(pattern: &str) -> anyhow::Result<Self> {
+        let words = pattern
+            .split('.')
+            .map(|word| match word {
+                "*" => Ok(Word::One),
+                "#" => Ok(Word::Any),
+                "" => Err(anyhow!("topic `{}` has empty word", pattern)),
+                word if word.contains(['*', '#']) => Err(anyhow!(
+                    "topic `{}` has wildcard which is not a whole word",
+                    pattern
+                )),
+                word => Ok(Word::Exact(word.to_owned())),
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            pattern: pattern.to_owned(),
+            words,
+        })
+    }
+
+    /// Pattern without wildcards matching only topic equal to it
+    pub fn is_exact(&self) -> bool {
+        self.words.iter().all(|word| matches!(word, Word::Exact(_)))
+    }
+
+    /// Topic matches pattern
+    pub fn matches(&self, topic: &str) -> bool {
+        let topic: Vec<&str> = topic.split('.').collect();
+        matches_words(&self.words, &topic)
+    }
+}
+
+fn matches_words(pattern: &[Word], topic: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => topic.is_empty(),
+        Some((Word::Any, rest)) => (0..=topic.len()).any(|i| matches_words(rest, &topic[i..])),
+        Some((Word::One, rest)) => !topic.is_empty() && matches_words(rest, &topic[1..]),
+        Some((Word::Exact(word), rest)) => {
+            topic.first() == Some(&word.as_str()) && matches_words(rest, &topic[1..])
+        }
+    }
+}
+
+impl fmt::Display for TopicPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(pattern: &str) -> TopicPattern {
+        TopicPattern::new(pattern).unwrap()
+    }
+
+    #[test]
+    fn star_matches_one_word() {
+        let load = pattern("metrics.*.load");
+        assert!(load.matches("metrics.cpu.load"));
+        assert!(!load.matches("metrics.load"));
+        assert!(!load.matches("metrics.cpu.core.load"));
+        assert!(pattern("*").matches("metrics"));
+        assert!(!pattern("*").matches("metrics.cpu"));
+        assert!(!pattern("metrics.*").matches("metrics"));
+    }
+
+    #[test]
+    fn hash_matches_any_words() {
+        let load = pattern("metrics.#.load");
+        assert!(load.matches("metrics.load"));
+        assert!(load.matches("metrics.cpu.load"));
+        assert!(load.matches("metrics.cpu.core.load"));
+        assert!(!load.matches("metrics.cpu"));
+        assert!(pattern("#").matches("metrics.cpu.load"));
+        assert!(pattern("#.load").matches("load"));
+        assert!(pattern("metrics.#").matches("metrics"));
+        assert!(!pattern("metrics.#").matches("metric"));
+    }
+
+    #[test]
+    fn exact_topic() {
+        let cpu = pattern("metrics.cpu");
+        assert!(cpu.is_exact());
+        assert!(cpu.matches("metrics.cpu"));
+        assert!(!cpu.matches("metrics"));
+        assert!(!cpu.matches("metrics.cpu.load"));
+        assert!(!pattern("metrics.*").is_exact());
+        assert!(!pattern("metrics.#").is_exact());
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        for invalid in &["", "metrics.", "metrics..cpu", "metrics.cpu*", "#metrics"] {
+            assert!(TopicPattern::new(invalid).is_err(), "{}", invalid);
+        }
+    }
+}